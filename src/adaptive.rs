@@ -0,0 +1,170 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::vec3::Color;
+
+// z-score of the 95% confidence interval
+const CONFIDENCE_Z: f64 = 1.96;
+// keeps the relative error of nearly black pixels from never converging
+const MIN_MEAN: f64 = 0.01;
+
+/// Running mean and variance of the luminance of one pixel's samples (Welford's algorithm).
+#[derive(Clone, Copy, Default)]
+pub struct PixelStatistics {
+    count: u32,
+    mean: f64,
+    m2: f64,
+}
+
+impl PixelStatistics {
+    pub fn from_parts(count: u32, mean: f64, m2: f64) -> Self {
+        Self { count, mean, m2 }
+    }
+
+    /// sum of squared differences from the mean
+    pub fn m2(&self) -> f64 {
+        self.m2
+    }
+
+    pub fn add(&mut self, color: Color) {
+        let value = color.luminance();
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    pub fn variance(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        self.m2 / (self.count - 1) as f64
+    }
+
+    /// half-width of the 95% confidence interval of the mean
+    pub fn confidence_interval(&self) -> f64 {
+        CONFIDENCE_Z * (self.variance() / self.count as f64).sqrt()
+    }
+}
+
+/// `max_samples` of renders that run until they are stopped, like time limited ones
+pub const UNLIMITED_SAMPLES: u32 = u32::MAX;
+
+/// How many samples a pixel may take.
+#[derive(Clone, Copy, Debug)]
+pub struct SampleBudget {
+    pub min_samples: u32,
+    pub max_samples: u32,
+    /// pixel is done once its confidence interval is below this fraction of the mean
+    pub threshold: f64,
+}
+
+impl SampleBudget {
+    /// every pixel gets exactly `samples`
+    pub fn fixed(samples: u32) -> Self {
+        Self {
+            min_samples: samples,
+            max_samples: samples,
+            threshold: 0.,
+        }
+    }
+
+    pub fn adaptive(min_samples: u32, max_samples: u32, threshold: f64) -> Self {
+        Self {
+            min_samples: min_samples.min(max_samples),
+            max_samples,
+            threshold,
+        }
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.max_samples == UNLIMITED_SAMPLES
+    }
+
+    /// the same budget stopping at `max_samples` at the latest
+    pub fn limited_to(self, max_samples: u32) -> Self {
+        Self {
+            max_samples: self.max_samples.min(max_samples),
+            ..self
+        }
+    }
+
+    pub fn is_done(&self, statistics: &PixelStatistics) -> bool {
+        if statistics.count() >= self.max_samples {
+            return true;
+        }
+        if statistics.count() < self.min_samples {
+            return false;
+        }
+        statistics.confidence_interval() <= self.threshold * statistics.mean().max(MIN_MEAN)
+    }
+}
+
+/// Writes a ppm image of how many samples every pixel took, from black (none) through
+/// red and yellow to white (`max_samples`). Rows go from top to bottom.
+pub fn write_heatmap(
+    path: &str,
+    width: i32,
+    height: i32,
+    samples: &[u32],
+    max_samples: u32,
+) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "P3\n{} {}\n255", width, height)?;
+    for row in samples.chunks(width as usize) {
+        for &count in row {
+            let t = 3. * count as f64 / max_samples.max(1) as f64;
+            let channel = |offset: f64| (255. * (t - offset).clamp(0., 1.)) as i32;
+            write!(file, "{} {} {} ", channel(0.), channel(1.), channel(2.))?;
+        }
+        writeln!(file)?;
+    }
+    file.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::compare_floats;
+
+    #[test]
+    fn test_statistics() {
+        let mut statistics = PixelStatistics::default();
+        for value in [1., 2., 3., 4.] {
+            statistics.add(Color::new(value, value, value));
+        }
+        assert_eq!(statistics.count(), 4);
+        assert!(compare_floats(statistics.mean(), 2.5));
+        assert!(compare_floats(statistics.variance(), 5. / 3.));
+    }
+
+    #[test]
+    fn test_budget() {
+        let budget = SampleBudget::adaptive(4, 8, 0.05);
+        let mut statistics = PixelStatistics::default();
+        for _ in 0..3 {
+            statistics.add(Color::new(0.5, 0.5, 0.5));
+        }
+        assert!(!budget.is_done(&statistics));
+        statistics.add(Color::new(0.5, 0.5, 0.5));
+        // no variance at all
+        assert!(budget.is_done(&statistics));
+
+        let mut noisy = PixelStatistics::default();
+        for index in 0..7 {
+            let value = (index % 2) as f64;
+            noisy.add(Color::new(value, value, value));
+        }
+        assert!(!budget.is_done(&noisy));
+        noisy.add(Color::new(1., 1., 1.));
+        assert!(budget.is_done(&noisy));
+    }
+}
@@ -0,0 +1,389 @@
+use std::fs;
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+use crate::transform::Transform;
+use crate::vec3::{Point3, Vec3};
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Straight lines between keyframes, motion changes abruptly at every key
+    Linear,
+    /// Smooth curve through the keyframes
+    CatmullRom,
+}
+
+/// Something keyframes can set. It is blended between keys component by component,
+/// packed into three vectors.
+pub trait Keyed: Copy {
+    /// what a line of a keyframe file holds after the frame, e.g. `x y z`
+    const FORMAT: &'static str;
+
+    fn to_vectors(&self) -> [Vec3; 3];
+    fn from_vectors(vectors: [Vec3; 3]) -> Self;
+    /// None if the numbers of a line of a keyframe file do not fit `FORMAT` or are out
+    /// of range
+    fn from_values(values: &[f64]) -> Option<Self>;
+
+    /// Keeps a value interpolated between the keys `start` and `end` valid where the
+    /// curve overshoots them, by default it is taken as it is.
+    fn limit(self, _start: &Self, _end: &Self) -> Self {
+        self
+    }
+}
+
+/// What a keyframe sets of the camera.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraPose {
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    /// vertical field of view in degrees
+    pub vfov: f64,
+    pub focus_distance: f64,
+}
+
+impl Keyed for CameraPose {
+    const FORMAT: &'static str = "from_x from_y from_z  at_x at_y at_z  vfov  focus_distance";
+
+    fn to_vectors(&self) -> [Vec3; 3] {
+        // scalars ride along in a vector of their own
+        let scalars = Vec3::new(self.vfov, self.focus_distance, 0.);
+        [self.lookfrom, self.lookat, scalars]
+    }
+
+    fn from_vectors([lookfrom, lookat, scalars]: [Vec3; 3]) -> Self {
+        Self {
+            lookfrom,
+            lookat,
+            vfov: scalars.x,
+            focus_distance: scalars.y,
+        }
+    }
+
+    fn from_values(values: &[f64]) -> Option<Self> {
+        let [fx, fy, fz, ax, ay, az, vfov, focus_distance] = *values else {
+            return None;
+        };
+        Some(Self {
+            lookfrom: Point3::new(fx, fy, fz),
+            lookat: Point3::new(ax, ay, az),
+            vfov,
+            focus_distance,
+        })
+    }
+}
+
+impl Keyed for Transform {
+    const FORMAT: &'static str = "tx ty tz  rx ry rz  sx sy sz";
+
+    fn to_vectors(&self) -> [Vec3; 3] {
+        [self.translation, self.rotation, self.scale]
+    }
+
+    fn from_vectors([translation, rotation, scale]: [Vec3; 3]) -> Self {
+        Self {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    /// a curve through small scales may dip to zero and below, it stops at the
+    /// smaller of the keys
+    fn limit(self, start: &Self, end: &Self) -> Self {
+        let smallest = |component: fn(&Vec3) -> f64| {
+            component(&self.scale).max(component(&start.scale).min(component(&end.scale)))
+        };
+        Self {
+            scale: Vec3::new(smallest(|v| v.x), smallest(|v| v.y), smallest(|v| v.z)),
+            ..self
+        }
+    }
+
+    fn from_values(values: &[f64]) -> Option<Self> {
+        let [tx, ty, tz, rx, ry, rz, sx, sy, sz] = *values else {
+            return None;
+        };
+        // a zero scale flattens the object, its inverse is infinite
+        let valid = values.iter().all(|value| value.is_finite())
+            && [sx, sy, sz].iter().all(|&scale| scale > 0.);
+        if !valid {
+            return None;
+        }
+        Some(Self {
+            translation: Vec3::new(tx, ty, tz),
+            rotation: Vec3::new(rx, ry, rz),
+            scale: Vec3::new(sx, sy, sz),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keyframe<T> {
+    pub frame: f64,
+    pub value: T,
+}
+
+/// Values set at some frames and interpolated in between. Before the first and after
+/// the last keyframe the value holds still.
+pub struct Track<T> {
+    keys: Vec<Keyframe<T>>,
+    interpolation: Interpolation,
+}
+
+/// Where the camera is and what it looks at over time.
+pub type CameraPath = Track<CameraPose>;
+/// How an object is placed over time.
+pub type TransformPath = Track<Transform>;
+
+impl<T: Keyed> Track<T> {
+    /// None without keyframes
+    pub fn new(mut keys: Vec<Keyframe<T>>, interpolation: Interpolation) -> Option<Self> {
+        if keys.is_empty() {
+            return None;
+        }
+        keys.sort_by(|a, b| a.frame.total_cmp(&b.frame));
+        // of keys at the same frame the last one wins
+        keys.dedup_by(|later, earlier| {
+            if later.frame == earlier.frame {
+                *earlier = *later;
+                true
+            } else {
+                false
+            }
+        });
+        Some(Self {
+            keys,
+            interpolation,
+        })
+    }
+
+    /// Reads keyframes from a text file, one per line: the frame followed by the
+    /// values in `T::FORMAT`. Empty lines and everything after a # are ignored.
+    pub fn load(path: &str, interpolation: Interpolation) -> Result<Self> {
+        let invalid = |line: usize, message: &str| {
+            Error::InvalidKeyframes(format!("{}:{}: {}", path, line, message))
+        };
+        let mut keys = Vec::new();
+        for (index, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line_number = index + 1;
+            let content = line.split('#').next().unwrap_or_default();
+            if content.trim().is_empty() {
+                continue;
+            }
+            let values = content
+                .split_whitespace()
+                .map(|value| value.parse::<f64>())
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .map_err(|_| invalid(line_number, "not a number"))?;
+            let value = values.get(1..).and_then(T::from_values).ok_or_else(|| {
+                invalid(
+                    line_number,
+                    &format!("expected valid `frame  {}`", T::FORMAT),
+                )
+            })?;
+            keys.push(Keyframe {
+                frame: values[0],
+                value,
+            });
+        }
+        Self::new(keys, interpolation)
+            .ok_or_else(|| Error::InvalidKeyframes(format!("{}: no keyframes", path)))
+    }
+
+    pub fn at(&self, frame: f64) -> T {
+        let keys = &self.keys;
+        let last = keys.len() - 1;
+        // the segment between keys[i] and keys[i + 1] holding the frame
+        let i = keys.partition_point(|key| key.frame <= frame);
+        if i == 0 {
+            return keys[0].value;
+        }
+        if i > last {
+            return keys[last].value;
+        }
+        let i = i - 1;
+        let (start, end) = (&keys[i], &keys[i + 1]);
+        let length = end.frame - start.frame;
+        let t = (frame - start.frame) / length;
+        let (p0, p1) = (start.value.to_vectors(), end.value.to_vectors());
+
+        let vectors = match self.interpolation {
+            Interpolation::Linear => {
+                [0, 1, 2].map(|component| p0[component] + (p1[component] - p0[component]) * t)
+            }
+            Interpolation::CatmullRom => {
+                // the curve passes the keys with the slope between their neighbours,
+                // the first and the last key look only at the one next to them
+                let before = &keys[i.saturating_sub(1)];
+                let after = &keys[(i + 2).min(last)];
+                let (previous, next) = (before.value.to_vectors(), after.value.to_vectors());
+                [0, 1, 2].map(|component| {
+                    let start_slope = (p1[component] - previous[component])
+                        * (length / (end.frame - before.frame));
+                    let end_slope =
+                        (next[component] - p0[component]) * (length / (after.frame - start.frame));
+                    hermite(p0[component], p1[component], start_slope, end_slope, t)
+                })
+            }
+        };
+        T::from_vectors(vectors).limit(&start.value, &end.value)
+    }
+}
+
+/// cubic from `p0` to `p1` with the slopes `m0` and `m1` over t in [0, 1]
+fn hermite(p0: Vec3, p1: Vec3, m0: Vec3, m1: Vec3, t: f64) -> Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    p0 * (2. * t3 - 3. * t2 + 1.)
+        + m0 * (t3 - 2. * t2 + t)
+        + p1 * (-2. * t3 + 3. * t2)
+        + m1 * (t3 - t2)
+}
+
+/// Frames of an image sequence written like a Rust range: `start..end` leaves out
+/// `end`, `start..=end` includes it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameRange {
+    pub start: u32,
+    /// exclusive
+    pub end: u32,
+}
+
+impl FrameRange {
+    pub fn frames(&self) -> std::ops::Range<u32> {
+        self.start..self.end
+    }
+
+    pub fn len(&self) -> u32 {
+        self.end.saturating_sub(self.start)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl FromStr for FrameRange {
+    type Err = String;
+
+    fn from_str(text: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || format!("'{}' is not a frame range like 0..120", text);
+        let (start, end) = text.split_once("..").ok_or_else(invalid)?;
+        let (end, inclusive) = match end.strip_prefix('=') {
+            Some(end) => (end, true),
+            None => (end, false),
+        };
+        let start: u32 = start.trim().parse().map_err(|_| invalid())?;
+        let mut end: u32 = end.trim().parse().map_err(|_| invalid())?;
+        if inclusive {
+            end = end.checked_add(1).ok_or_else(invalid)?;
+        }
+        if end <= start {
+            return Err(format!("frame range '{}' is empty", text));
+        }
+        Ok(Self { start, end })
+    }
+}
+
+/// Path of one image of a sequence: a run of # in `template` is replaced by the frame
+/// number padded with zeros to its length, without # the number goes before the
+/// extension, e.g. `out.ppm` becomes `out.0007.ppm`.
+pub fn frame_path(template: &str, frame: u32) -> String {
+    if let Some(start) = template.find('#') {
+        let width = template[start..]
+            .find(|c| c != '#')
+            .unwrap_or(template.len() - start);
+        return format!(
+            "{}{:0width$}{}",
+            &template[..start],
+            frame,
+            &template[start + width..],
+            width = width
+        );
+    }
+    let file_start = template.rfind('/').map_or(0, |slash| slash + 1);
+    match template[file_start..].rfind('.') {
+        Some(dot) if dot > 0 => {
+            let dot = file_start + dot;
+            format!("{}.{:04}{}", &template[..dot], frame, &template[dot..])
+        }
+        _ => format!("{}.{:04}", template, frame),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(frame: f64, x: f64, vfov: f64) -> Keyframe<CameraPose> {
+        Keyframe {
+            frame,
+            value: CameraPose {
+                lookfrom: Point3::new(x, 0., 0.),
+                lookat: Point3::origin(),
+                vfov,
+                focus_distance: 10.,
+            },
+        }
+    }
+
+    #[test]
+    fn test_interpolation_passes_keys() {
+        let keys = vec![key(10., 4., 40.), key(0., 0., 20.), key(20., 0., 30.)];
+        for interpolation in [Interpolation::Linear, Interpolation::CatmullRom] {
+            let path = CameraPath::new(keys.clone(), interpolation).unwrap();
+            for key in &keys {
+                assert_eq!(path.at(key.frame), key.value);
+            }
+            // the camera holds still outside of the keys
+            assert_eq!(path.at(-5.), keys[1].value);
+            assert_eq!(path.at(25.), keys[2].value);
+        }
+
+        let linear = CameraPath::new(keys.clone(), Interpolation::Linear).unwrap();
+        assert_eq!(linear.at(5.).lookfrom, Point3::new(2., 0., 0.));
+        assert_eq!(linear.at(15.).vfov, 35.);
+        // the curve rounds off the turn at the middle key
+        let smooth = CameraPath::new(keys, Interpolation::CatmullRom).unwrap();
+        assert!(smooth.at(8.).lookfrom.x > linear.at(8.).lookfrom.x);
+    }
+
+    #[test]
+    fn test_scales_stay_above_zero() {
+        let values = |sx: f64| [0., 0., 0., 0., 0., 0., sx, 1., 1.];
+        assert!(Transform::from_values(&values(0.5)).is_some());
+        assert!(Transform::from_values(&values(0.)).is_none());
+        assert!(Transform::from_values(&values(-1.)).is_none());
+        assert!(Transform::from_values(&values(f64::INFINITY)).is_none());
+
+        let key = |frame: f64, sx: f64| Keyframe {
+            frame,
+            value: Transform::from_values(&values(sx)).unwrap(),
+        };
+        // shrinking fast into a key that holds, the curve would overshoot below zero
+        let keys = vec![key(0., 5.), key(1., 0.2), key(2., 0.2)];
+        let path = TransformPath::new(keys, Interpolation::CatmullRom).unwrap();
+        for step in 0..=20 {
+            let scale = path.at(step as f64 / 10.).scale.x;
+            assert!(scale >= 0.2, "{}", scale);
+        }
+    }
+
+    #[test]
+    fn test_frame_range() {
+        let range: FrameRange = "0..120".parse().unwrap();
+        assert_eq!((range.start, range.end, range.len()), (0, 120, 120));
+        let range: FrameRange = "5..=7".parse().unwrap();
+        assert_eq!(range.frames().collect::<Vec<_>>(), vec![5, 6, 7]);
+        assert!("7..7".parse::<FrameRange>().is_err());
+        assert!("12".parse::<FrameRange>().is_err());
+    }
+
+    #[test]
+    fn test_frame_path() {
+        assert_eq!(frame_path("turntable_###.ppm", 7), "turntable_007.ppm");
+        assert_eq!(frame_path("out.ppm", 12), "out.0012.ppm");
+        assert_eq!(frame_path("renders.v2/out", 3), "renders.v2/out.0003");
+    }
+}
@@ -0,0 +1,218 @@
+use std::f64::consts::PI;
+use std::fs;
+
+use crate::error::{Error, Result};
+use crate::sampler;
+use crate::vec3::Vec3;
+
+/// Shape of the opening of a thin lens, it gives out-of-focus highlights their shape.
+pub enum Aperture {
+    Circle,
+    /// regular polygon formed by `blades` straight blades, `rotation` in degrees
+    Polygon {
+        blades: u32,
+        rotation: f64,
+    },
+    Mask(ApertureMask),
+}
+
+impl Aperture {
+    /// Maps a point in [0,1)^2 to a point of the aperture (z is 0). Circles and polygons
+    /// fit into the unit disk, masks cover the square around it.
+    pub fn sample(&self, (u, v): (f64, f64)) -> Vec3 {
+        match self {
+            Aperture::Circle => sampler::sample_unit_disk((u, v)),
+            Aperture::Polygon { blades, rotation } => {
+                let blades = (*blades).max(3);
+                // u picks one of the triangles between the center and two corners
+                let scaled = u * blades as f64;
+                let triangle = (scaled as u32).min(blades - 1);
+                let u = scaled - triangle as f64;
+
+                let corner = |index: u32| {
+                    let angle = rotation.to_radians() + 2. * PI * index as f64 / blades as f64;
+                    Vec3::new(angle.cos(), angle.sin(), 0.)
+                };
+                let root = u.sqrt();
+                root * ((1. - v) * corner(triangle) + v * corner(triangle + 1))
+            }
+            Aperture::Mask(mask) => mask.sample((u, v)),
+        }
+    }
+}
+
+/// Aperture given by a grayscale image, brighter pixels let through more light.
+/// The image is stretched over the square around the unit disk.
+pub struct ApertureMask {
+    width: usize,
+    height: usize,
+    /// running sum of the pixel weights normalized to end at 1, row by row from the top
+    cdf: Vec<f64>,
+}
+
+impl ApertureMask {
+    /// None if no pixel lets light through
+    pub fn new(width: usize, height: usize, weights: &[f64]) -> Option<Self> {
+        let mut cdf = Vec::with_capacity(weights.len());
+        let mut sum = 0.;
+        for weight in weights {
+            sum += weight.max(0.);
+            cdf.push(sum);
+        }
+        if sum <= 0. {
+            return None;
+        }
+        for value in cdf.iter_mut() {
+            *value /= sum;
+        }
+        Some(Self { width, height, cdf })
+    }
+
+    /// reads a ppm image (P3 or P6), the luminance of a pixel is its weight
+    pub fn load(path: &str) -> Result<Self> {
+        let invalid = |message: &str| Error::InvalidImage(format!("{}: {}", path, message));
+        let bytes = fs::read(path)?;
+
+        // the header is whitespace separated, comments run from # to the end of a line
+        let mut fields = Vec::with_capacity(4);
+        let mut position = 0;
+        while fields.len() < 4 {
+            while position < bytes.len() && bytes[position].is_ascii_whitespace() {
+                position += 1;
+            }
+            if position < bytes.len() && bytes[position] == b'#' {
+                while position < bytes.len() && bytes[position] != b'\n' {
+                    position += 1;
+                }
+                continue;
+            }
+            let start = position;
+            while position < bytes.len() && !bytes[position].is_ascii_whitespace() {
+                position += 1;
+            }
+            if start == position {
+                return Err(invalid("truncated header"));
+            }
+            fields.push(String::from_utf8_lossy(&bytes[start..position]).into_owned());
+        }
+        let number = |text: &str| {
+            text.parse::<usize>()
+                .map_err(|_| invalid("bad number in header"))
+        };
+        let width = number(&fields[1])?;
+        let height = number(&fields[2])?;
+        let max_value = number(&fields[3])?.max(1) as f64;
+
+        // a single whitespace byte separates the header from binary pixels
+        let binary = bytes.get(position + 1..).unwrap_or_default();
+        let values: Vec<f64> = match fields[0].as_str() {
+            "P3" => String::from_utf8_lossy(&bytes[position..])
+                .split_ascii_whitespace()
+                .map(|value| value.parse::<f64>().map_err(|_| invalid("bad pixel value")))
+                .collect::<Result<_>>()?,
+            "P6" if max_value < 256. => binary.iter().map(|&byte| byte as f64).collect(),
+            "P6" => binary
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]) as f64)
+                .collect(),
+            _ => return Err(invalid("not a ppm image")),
+        };
+        let pixels = width
+            .checked_mul(height)
+            .filter(|pixels| pixels.checked_mul(3).is_some())
+            .ok_or_else(|| invalid("image too large"))?;
+        if values.len() < 3 * pixels {
+            return Err(invalid("fewer pixels than the header says"));
+        }
+
+        let weights: Vec<f64> = values
+            .chunks_exact(3)
+            .take(pixels)
+            .map(|rgb| Vec3::new(rgb[0], rgb[1], rgb[2]).luminance() / max_value)
+            .collect();
+        Self::new(width, height, &weights).ok_or_else(|| invalid("the mask is black"))
+    }
+
+    /// picks a pixel with a probability proportional to its weight and a point in it
+    fn sample(&self, (u, v): (f64, f64)) -> Vec3 {
+        let index = self
+            .cdf
+            .partition_point(|&value| value <= u)
+            .min(self.cdf.len() - 1);
+        let start = if index == 0 { 0. } else { self.cdf[index - 1] };
+        // what is left of u inside the pixel places the point across it
+        let across = ((u - start) / (self.cdf[index] - start)).clamp(0., 1.);
+
+        let col = (index % self.width) as f64 + across;
+        let row = (index / self.width) as f64 + v;
+        Vec3::new(
+            2. * col / self.width as f64 - 1.,
+            1. - 2. * row / self.height as f64,
+            0.,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(size: usize) -> impl Iterator<Item = (f64, f64)> {
+        (0..size * size).map(move |index| {
+            (
+                ((index % size) as f64 + 0.5) / size as f64,
+                ((index / size) as f64 + 0.5) / size as f64,
+            )
+        })
+    }
+
+    #[test]
+    fn test_polygon_inside_edges() {
+        let blades = 6;
+        let aperture = Aperture::Polygon {
+            blades,
+            rotation: 15.,
+        };
+        // distance from the center to the middle of an edge
+        let apothem = (PI / blades as f64).cos();
+        for sample in grid(32) {
+            let point = aperture.sample(sample);
+            for edge in 0..blades {
+                let angle = (15. + 60. * (edge as f64 + 0.5)).to_radians();
+                let normal = Vec3::new(angle.cos(), angle.sin(), 0.);
+                assert!(point.dot(&normal) <= apothem + 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_mask_samples_lit_pixels() {
+        // only the upper right pixel of 2x2 is open
+        let mask = ApertureMask::new(2, 2, &[0., 1., 0., 0.]).unwrap();
+        let aperture = Aperture::Mask(mask);
+        for sample in grid(8) {
+            let point = aperture.sample(sample);
+            assert!(point.x >= 0. && point.x <= 1., "{}", point);
+            assert!(point.y >= 0. && point.y <= 1., "{}", point);
+        }
+        assert!(ApertureMask::new(2, 1, &[0., 0.]).is_none());
+    }
+
+    #[test]
+    fn test_load_mask() {
+        let path = std::env::temp_dir().join("ray-tracing-aperture-test.ppm");
+        let path = path.to_str().unwrap();
+        fs::write(path, "P3\n# comment\n2 1\n255\n0 0 0 255 255 255\n").unwrap();
+        let mask = ApertureMask::load(path);
+        fs::remove_file(path).unwrap();
+        let mask = mask.unwrap();
+        assert_eq!((mask.width, mask.height), (2, 1));
+        assert_eq!(mask.cdf, vec![0., 1.]);
+
+        let huge = format!("P3\n{} 4\n255\n0 0 0\n", usize::MAX / 2);
+        fs::write(path, huge).unwrap();
+        let mask = ApertureMask::load(path);
+        fs::remove_file(path).unwrap();
+        assert!(matches!(mask, Err(Error::InvalidImage(_))));
+    }
+}
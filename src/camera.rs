@@ -1,8 +1,62 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::aperture::Aperture;
+use crate::hittable::Hittable;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::utils;
 use crate::vec3::{Point3, Vec3};
 
-pub struct Camera {
+/// Maps a point of the image to the ray seen through it.
+pub trait Camera: Send + Sync {
+    /// (u, v) goes from (0, 0) in the lower left corner of the image to (1, 1) in the
+    /// upper right one. None if no ray passes through that point, it stays black.
+    fn get_ray(&self, u: f64, v: f64, sampler: &mut dyn Sampler) -> Option<Ray>;
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Projection {
+    /// Pinhole or thin lens, far things look smaller
+    Perspective,
+    /// Parallel rays, sizes do not change with distance
+    Orthographic,
+    /// Full 360x180 degrees panorama, longitude along x and latitude along y
+    Equirectangular,
+    /// Circular image of a fisheye lens inscribed into the image height
+    Fisheye,
+}
+
+/// Orthonormal basis of a camera at lookfrom: u to the right, v up and w backwards.
+pub struct Frame {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Frame {
+    pub fn new(lookfrom: Point3, lookat: Point3, vup: Vec3) -> Self {
+        let w = (lookfrom - lookat).unit_vector();
+        let u = vup.cross(&w).unit_vector();
+        let v = w.cross(&u);
+        Self { u, v, w }
+    }
+
+    /// `direction` given as right, up and forward components
+    pub fn to_world(&self, right: f64, up: f64, forward: f64) -> Vec3 {
+        right * self.u + up * self.v - forward * self.w
+    }
+}
+
+/// Focus distance that puts the first surface of `target` hit by `ray` in focus, for
+/// a camera at the origin of the ray oriented by `frame`. None if the ray misses.
+pub fn autofocus(target: &dyn Hittable, ray: &Ray, frame: &Frame) -> Option<f64> {
+    let record = target.hit(ray, 0.001, f64::INFINITY)?;
+    // the focal plane is perpendicular to the view axis
+    Some((record.point - ray.origin).dot(&-frame.w))
+}
+
+pub struct ThinLensCamera {
     origin: Point3,
     lower_left_corner: Point3,
     horizontal: Vec3,
@@ -10,9 +64,15 @@ pub struct Camera {
     u: Vec3,
     v: Vec3,
     lens_radius: f64,
+    aspect_ratio: f64,
+    aperture: Aperture,
+    /// horizontal compression of the aperture by an anamorphic lens
+    squeeze: f64,
+    /// shift of the lens barrel per unit of distance from the image center
+    vignetting: f64,
 }
 
-impl Camera {
+impl ThinLensCamera {
     pub fn new(
         lookfrom: Point3,
         lookat: Point3,
@@ -27,9 +87,7 @@ impl Camera {
         let viewport_height: f64 = 2.0 * h;
         let viewport_width: f64 = viewport_height * aspect_ratio;
 
-        let w = (lookfrom - lookat).unit_vector();
-        let u = vup.cross(&w).unit_vector();
-        let v = w.cross(&u);
+        let Frame { u, v, w } = Frame::new(lookfrom, lookat, vup);
 
         let origin = lookfrom;
         let horizontal = focus_dist * viewport_width * u;
@@ -45,16 +103,434 @@ impl Camera {
             u,
             v,
             lens_radius,
+            aspect_ratio,
+            aperture: Aperture::Circle,
+            squeeze: 1.,
+            vignetting: 0.,
         }
     }
 
-    pub fn get_ray(&self, u: f64, v: f64) -> Ray {
-        let rd = self.lens_radius * Vec3::random_in_unit_disk();
+    pub fn with_aperture(self, aperture: Aperture) -> Self {
+        Self { aperture, ..self }
+    }
+
+    /// An anamorphic lens squeezed the image horizontally by `squeeze` (e.g. 2) and
+    /// the aperture with it, out-of-focus highlights become tall ovals. `squeeze` has to
+    /// be above zero.
+    pub fn with_anamorphic_squeeze(self, squeeze: f64) -> Self {
+        Self { squeeze, ..self }
+    }
+
+    /// Optical vignetting: the lens barrel cuts off the aperture away from the image
+    /// center, highlights there become cat-eye shaped and the corners darker. At the
+    /// middle of the top edge the barrel is shifted by `vignetting` aperture radii.
+    pub fn with_optical_vignetting(self, vignetting: f64) -> Self {
+        Self { vignetting, ..self }
+    }
+}
+
+impl Camera for ThinLensCamera {
+    fn get_ray(&self, u: f64, v: f64, sampler: &mut dyn Sampler) -> Option<Ray> {
+        let mut point = self.aperture.sample(sampler.get_2d());
+        point.x /= self.squeeze;
+        if self.vignetting > 0. && self.lens_radius > 0. {
+            // the barrel is a circle as large as the aperture, shifted towards the center
+            let barrel_x = -(2. * u - 1.) * self.aspect_ratio * self.vignetting;
+            let barrel_y = -(2. * v - 1.) * self.vignetting;
+            if (point.x - barrel_x).powi(2) + (point.y - barrel_y).powi(2) > 1. {
+                return None;
+            }
+        }
+        let rd = self.lens_radius * point;
         let offset = self.u * rd.x + self.v * rd.y;
 
-        Ray::new(
+        Some(Ray::new(
             self.origin + offset,
             self.lower_left_corner + u * self.horizontal + v * self.vertical - self.origin - offset,
+        ))
+    }
+}
+
+/// Settings of a real camera, they give the field of view and aperture of a
+/// `ThinLensCamera` and how bright the image is. Scene units are meters. Colors keep
+/// their scale, where a bright sky is about 1, see `exposure`.
+#[derive(Clone, Copy, Debug)]
+pub struct PhysicalCamera {
+    /// in millimeters
+    pub focal_length: f64,
+    /// in millimeters
+    pub sensor_width: f64,
+    pub sensor_height: f64,
+    pub f_number: f64,
+    /// exposure time in seconds
+    pub shutter: f64,
+    pub iso: f64,
+}
+
+/// Settings at which a `PhysicalCamera` renders the scene as bright as a camera without
+/// them, f/2.8 at 1/60 s and ISO 100.
+pub const REFERENCE_EXPOSURE: PhysicalCamera = PhysicalCamera {
+    focal_length: 50.,
+    sensor_width: 36.,
+    sensor_height: 24.,
+    f_number: 2.8,
+    shutter: 1. / 60.,
+    iso: 100.,
+};
+
+impl PhysicalCamera {
+    /// vertical field of view in degrees of an image fitted into the sensor
+    pub fn vfov(&self, aspect_ratio: f64) -> f64 {
+        let height = if aspect_ratio >= self.sensor_width / self.sensor_height {
+            self.sensor_width / aspect_ratio
+        } else {
+            self.sensor_height
+        };
+        2. * (height / (2. * self.focal_length)).atan().to_degrees()
+    }
+
+    /// diameter of the entrance pupil in meters
+    pub fn aperture(&self) -> f64 {
+        self.focal_length / self.f_number / 1000.
+    }
+
+    /// Factor from rendered colors to film values, relative to `REFERENCE_EXPOSURE`
+    /// that leaves them as they are. Every stop more light doubles it. Renders hold the
+    /// light reaching the camera, applying this to their films or images is up to the
+    /// caller.
+    pub fn exposure(&self) -> f64 {
+        let reference = REFERENCE_EXPOSURE.exposure_value();
+        2f64.powf(reference - self.exposure_value())
+    }
+
+    /// exposure value at ISO 100, higher for less light
+    fn exposure_value(&self) -> f64 {
+        (self.f_number.powi(2) / self.shutter * 100. / self.iso).log2()
+    }
+}
+
+/// Parallel rays along lookat - lookfrom that start on a rectangle centered on lookfrom.
+pub struct OrthographicCamera {
+    lower_left_corner: Point3,
+    horizontal: Vec3,
+    vertical: Vec3,
+    direction: Vec3,
+}
+
+impl OrthographicCamera {
+    pub fn new(
+        lookfrom: Point3,
+        lookat: Point3,
+        vup: Vec3,
+        view_width: f64,
+        aspect_ratio: f64,
+    ) -> Self {
+        let frame = Frame::new(lookfrom, lookat, vup);
+        let horizontal = view_width * frame.u;
+        let vertical = view_width / aspect_ratio * frame.v;
+
+        Self {
+            lower_left_corner: lookfrom - horizontal / 2.0 - vertical / 2.0,
+            horizontal,
+            vertical,
+            direction: -frame.w,
+        }
+    }
+}
+
+impl Camera for OrthographicCamera {
+    fn get_ray(&self, u: f64, v: f64, _sampler: &mut dyn Sampler) -> Option<Ray> {
+        Some(Ray::new(
+            self.lower_left_corner + u * self.horizontal + v * self.vertical,
+            self.direction,
+        ))
+    }
+}
+
+/// Sees every direction from lookfrom, lookat is in the center of the image and vup
+/// points to its top edge. The image should be twice as wide as high, otherwise the
+/// panorama is stretched.
+pub struct EquirectangularCamera {
+    origin: Point3,
+    frame: Frame,
+}
+
+impl EquirectangularCamera {
+    pub fn new(lookfrom: Point3, lookat: Point3, vup: Vec3) -> Self {
+        Self {
+            origin: lookfrom,
+            frame: Frame::new(lookfrom, lookat, vup),
+        }
+    }
+}
+
+impl Camera for EquirectangularCamera {
+    fn get_ray(&self, u: f64, v: f64, _sampler: &mut dyn Sampler) -> Option<Ray> {
+        let longitude = (u - 0.5) * 2. * PI;
+        let latitude = (v - 0.5) * PI;
+        let direction = self.frame.to_world(
+            latitude.cos() * longitude.sin(),
+            latitude.sin(),
+            latitude.cos() * longitude.cos(),
+        );
+        Some(Ray::new(self.origin, direction))
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FisheyeMapping {
+    /// Distance from the center proportional to the angle from the axis
+    Equidistant,
+    /// Equal areas of the image cover equal solid angles
+    Equisolid,
+}
+
+/// Fisheye lens whose image circle touches the top and bottom edges of the image,
+/// the corners outside of it stay black.
+pub struct FisheyeCamera {
+    origin: Point3,
+    frame: Frame,
+    mapping: FisheyeMapping,
+    /// angle between the axis and the edge of the image circle
+    half_fov: f64,
+    aspect_ratio: f64,
+}
+
+impl FisheyeCamera {
+    pub fn new(
+        lookfrom: Point3,
+        lookat: Point3,
+        vup: Vec3,
+        mapping: FisheyeMapping,
+        fov: f64, // field-of-view across the image circle in degrees, up to 360
+        aspect_ratio: f64,
+    ) -> Self {
+        Self {
+            origin: lookfrom,
+            frame: Frame::new(lookfrom, lookat, vup),
+            mapping,
+            half_fov: utils::degrees_to_radians(fov.min(360.)) / 2.,
+            aspect_ratio,
+        }
+    }
+}
+
+impl Camera for FisheyeCamera {
+    fn get_ray(&self, u: f64, v: f64, _sampler: &mut dyn Sampler) -> Option<Ray> {
+        // position in the image circle of radius 1
+        let x = (2. * u - 1.) * self.aspect_ratio;
+        let y = 2. * v - 1.;
+        let radius = (x * x + y * y).sqrt();
+        if radius > 1. {
+            return None;
+        }
+
+        let theta = match self.mapping {
+            FisheyeMapping::Equidistant => radius * self.half_fov,
+            FisheyeMapping::Equisolid => {
+                2. * (radius * (self.half_fov / 2.).sin()).clamp(-1., 1.).asin()
+            }
+        };
+        let phi = y.atan2(x);
+        let direction = self.frame.to_world(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            theta.cos(),
+        );
+        Some(Ray::new(self.origin, direction))
+    }
+}
+
+/// Sends the rays of `camera` at times spread over the interval the shutter is open,
+/// moving objects are blurred along their path.
+pub struct ShutterCamera {
+    camera: Arc<dyn Camera>,
+    open: f64,
+    close: f64,
+}
+
+impl ShutterCamera {
+    /// `open` and `close` in frames, equal for a still image at that time
+    pub fn new(camera: Arc<dyn Camera>, open: f64, close: f64) -> Self {
+        Self {
+            camera,
+            open,
+            close,
+        }
+    }
+}
+
+impl Camera for ShutterCamera {
+    fn get_ray(&self, u: f64, v: f64, sampler: &mut dyn Sampler) -> Option<Ray> {
+        let mut ray = self.camera.get_ray(u, v, sampler)?;
+        ray.time = if self.close > self.open {
+            self.open + (self.close - self.open) * sampler.get_1d()
+        } else {
+            self.open
+        };
+        Some(ray)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::materials::Lambertian;
+    use crate::sampler::SamplerKind;
+    use crate::sphere::Sphere;
+    use crate::utils::compare_floats;
+
+    fn assert_direction(ray: Option<Ray>, expected: Vec3) {
+        let direction = ray.unwrap().direction.unit_vector();
+        assert!(
+            (direction - expected).length() < 1e-9,
+            "{} is not {}",
+            direction,
+            expected
+        );
+    }
+
+    fn camera_frame() -> (Point3, Point3, Vec3) {
+        (
+            Point3::new(0., 0., 5.),
+            Point3::origin(),
+            Vec3::new(0., 1., 0.),
         )
     }
+
+    #[test]
+    fn test_orthographic_rays_are_parallel() {
+        let (lookfrom, lookat, vup) = camera_frame();
+        let camera = OrthographicCamera::new(lookfrom, lookat, vup, 4., 2.);
+        let mut sampler = SamplerKind::Independent.create(1, 0);
+        let corner = camera.get_ray(0., 0., sampler.as_mut()).unwrap();
+        let center = camera.get_ray(0.5, 0.5, sampler.as_mut()).unwrap();
+        assert_eq!(corner.direction, Vec3::new(0., 0., -1.));
+        assert_eq!(center.direction, corner.direction);
+        assert_eq!(corner.origin, Point3::new(-2., -1., 5.));
+        assert_eq!(center.origin, Point3::new(0., 0., 5.));
+    }
+
+    #[test]
+    fn test_optical_vignetting() {
+        let (lookfrom, lookat, vup) = camera_frame();
+        let camera = ThinLensCamera::new(lookfrom, lookat, vup, 40., 1., 0.5, 5.)
+            .with_aperture(Aperture::Polygon {
+                blades: 5,
+                rotation: 0.,
+            })
+            .with_optical_vignetting(0.5);
+        let mut sampler = SamplerKind::Independent.create(1, 0);
+        let mut passed = |u, v| {
+            (0..1000)
+                .filter(|&index| {
+                    sampler.start_pixel_sample(0, 0, index);
+                    camera.get_ray(u, v, sampler.as_mut()).is_some()
+                })
+                .count()
+        };
+        assert_eq!(passed(0.5, 0.5), 1000);
+        let corner = passed(0., 0.);
+        assert!(corner > 0 && corner < 1000, "{}", corner);
+    }
+
+    #[test]
+    fn test_physical_camera() {
+        let camera = PhysicalCamera {
+            focal_length: 50.,
+            sensor_width: 36.,
+            sensor_height: 24.,
+            f_number: 2.,
+            shutter: 1. / 100.,
+            iso: 100.,
+        };
+        // 36x24 sensor fits a 3:2 image exactly
+        assert!(compare_floats(camera.vfov(1.5), 26.991466561591622));
+        assert!(compare_floats(camera.vfov(1.), camera.vfov(1.5)));
+        assert!(camera.vfov(2.) < camera.vfov(1.5));
+        assert!(compare_floats(camera.aperture(), 0.025));
+        // one stop more light doubles the exposure
+        let brighter = PhysicalCamera {
+            shutter: 1. / 50.,
+            ..camera
+        };
+        assert!(compare_floats(brighter.exposure(), 2. * camera.exposure()));
+        assert!(compare_floats(camera.exposure(), 2.8 * 2.8 * 60. / 400.));
+        assert!(compare_floats(REFERENCE_EXPOSURE.exposure(), 1.));
+    }
+
+    #[test]
+    fn test_autofocus() {
+        let (lookfrom, lookat, vup) = camera_frame();
+        let frame = Frame::new(lookfrom, lookat, vup);
+        let sphere = Sphere::new(
+            Point3::new(1., 0., 0.),
+            0.5,
+            Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+        );
+        let to_center = Ray::new(lookfrom, sphere.center(0.).unwrap() - lookfrom);
+        let distance = autofocus(&sphere, &to_center, &frame).unwrap();
+        // the front of the sphere is a bit closer than its center, 5 away along the axis
+        assert!(distance > 4.5 && distance < 5., "{}", distance);
+
+        let away = Ray::new(lookfrom, Vec3::new(0., 0., 1.));
+        assert!(autofocus(&sphere, &away, &frame).is_none());
+    }
+
+    #[test]
+    fn test_equirectangular() {
+        let (lookfrom, lookat, vup) = camera_frame();
+        let camera = EquirectangularCamera::new(lookfrom, lookat, vup);
+        let mut sampler = SamplerKind::Independent.create(1, 0);
+        assert_direction(
+            camera.get_ray(0.5, 0.5, sampler.as_mut()),
+            Vec3::new(0., 0., -1.),
+        );
+        assert_direction(
+            camera.get_ray(0.75, 0.5, sampler.as_mut()),
+            Vec3::new(1., 0., 0.),
+        );
+        assert_direction(
+            camera.get_ray(0., 0.5, sampler.as_mut()),
+            Vec3::new(0., 0., 1.),
+        );
+        assert_direction(
+            camera.get_ray(0.3, 1., sampler.as_mut()),
+            Vec3::new(0., 1., 0.),
+        );
+    }
+
+    #[test]
+    fn test_fisheye() {
+        let (lookfrom, lookat, vup) = camera_frame();
+        let mut sampler = SamplerKind::Independent.create(1, 0);
+        for mapping in [FisheyeMapping::Equidistant, FisheyeMapping::Equisolid] {
+            let camera = FisheyeCamera::new(lookfrom, lookat, vup, mapping, 180., 1.5);
+            assert_direction(
+                camera.get_ray(0.5, 0.5, sampler.as_mut()),
+                Vec3::new(0., 0., -1.),
+            );
+            // the top edge of the image circle is 90 degrees from the axis
+            assert_direction(
+                camera.get_ray(0.5, 1., sampler.as_mut()),
+                Vec3::new(0., 1., 0.),
+            );
+            assert!(camera.get_ray(0., 0., sampler.as_mut()).is_none());
+        }
+
+        let equidistant =
+            FisheyeCamera::new(lookfrom, lookat, vup, FisheyeMapping::Equidistant, 180., 1.);
+        let equisolid =
+            FisheyeCamera::new(lookfrom, lookat, vup, FisheyeMapping::Equisolid, 180., 1.);
+        let angle = |camera: &FisheyeCamera, sampler: &mut dyn Sampler| {
+            let direction = camera.get_ray(0.5, 0.75, sampler).unwrap().direction;
+            direction.unit_vector().y.asin()
+        };
+        assert!(compare_floats(
+            angle(&equidistant, sampler.as_mut()),
+            PI / 4.
+        ));
+        // 2 asin(sin(45°) / 2) = 41.4°
+        assert!(angle(&equisolid, sampler.as_mut()) < PI / 4.);
+    }
 }
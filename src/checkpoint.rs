@@ -0,0 +1,311 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+use clap::ValueEnum;
+
+use crate::adaptive::{PixelStatistics, SampleBudget};
+use crate::error::{Error, Result};
+use crate::render::{Accumulation, RenderSettings};
+use crate::scheduler::Tile;
+use crate::spectrum::Illuminant;
+use crate::vec3::Color;
+
+const MAGIC: &[u8; 8] = b"RTCKPT4\n";
+
+/// Writes the settings, the accumulated film and per-pixel statistics, so the render
+/// can be resumed later. `area` is the rendered window and `scene` a `fingerprint` of
+/// everything else the samples depend on. The file is written next to `path` and
+/// renamed over it, a crash while saving leaves the previous checkpoint intact.
+pub fn save(
+    path: &str,
+    area: &Tile,
+    scene: u64,
+    settings: &RenderSettings,
+    accumulation: &Accumulation,
+) -> Result<()> {
+    let temporary = format!("{}.tmp", path);
+    let mut out = BufWriter::new(File::create(&temporary)?);
+
+    out.write_all(MAGIC)?;
+    write_i32(&mut out, accumulation.width())?;
+    write_i32(&mut out, accumulation.height())?;
+    write_i32(&mut out, area.x0)?;
+    write_i32(&mut out, area.y0)?;
+    write_i32(&mut out, area.width)?;
+    write_i32(&mut out, area.height)?;
+    write_u64(&mut out, scene)?;
+
+    write_variant(&mut out, settings.sampler)?;
+    write_u64(&mut out, settings.seed)?;
+    write_u32(&mut out, settings.budget.min_samples)?;
+    write_u32(&mut out, settings.budget.max_samples)?;
+    write_f64(&mut out, settings.budget.threshold)?;
+    write_variant(&mut out, settings.filter)?;
+    write_f64(&mut out, settings.filter_radius)?;
+    write_bool(&mut out, settings.spectral)?;
+    write_illuminant(&mut out, settings.illuminant)?;
+
+    for pixel in accumulation.film.pixels() {
+        write_f64(&mut out, pixel.color.x)?;
+        write_f64(&mut out, pixel.color.y)?;
+        write_f64(&mut out, pixel.color.z)?;
+        write_f64(&mut out, pixel.weight)?;
+    }
+    for statistics in &accumulation.statistics {
+        write_u32(&mut out, statistics.count())?;
+        write_f64(&mut out, statistics.mean())?;
+        write_f64(&mut out, statistics.m2())?;
+    }
+
+    out.flush()?;
+    drop(out);
+    fs::rename(&temporary, path)?;
+    Ok(())
+}
+
+/// Reads a checkpoint saved by `save`, it has to be of a `width` x `height` image
+/// rendered in the same `area` of the same `scene`.
+pub fn load(
+    path: &str,
+    width: i32,
+    height: i32,
+    area: &Tile,
+    scene: u64,
+) -> Result<(RenderSettings, Accumulation)> {
+    let mut input = BufReader::new(File::open(path)?);
+
+    let mut magic = [0; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("not a checkpoint file"));
+    }
+    let saved_width = read_i32(&mut input)?;
+    let saved_height = read_i32(&mut input)?;
+    if (saved_width, saved_height) != (width, height) {
+        return Err(invalid_data(&format!(
+            "checkpoint is {}x{}, image is {}x{}",
+            saved_width, saved_height, width, height
+        )));
+    }
+    let saved_area = Tile {
+        x0: read_i32(&mut input)?,
+        y0: read_i32(&mut input)?,
+        width: read_i32(&mut input)?,
+        height: read_i32(&mut input)?,
+    };
+    if saved_area != *area {
+        return Err(invalid_data(&format!(
+            "checkpoint renders {}x{} pixels at {},{}, the crop is {}x{} pixels at {},{}",
+            saved_area.width,
+            saved_area.height,
+            saved_area.x0,
+            saved_area.y0,
+            area.width,
+            area.height,
+            area.x0,
+            area.y0
+        )));
+    }
+    if read_u64(&mut input)? != scene {
+        return Err(invalid_data(
+            "checkpoint was rendered with different scene or camera options",
+        ));
+    }
+
+    let settings = RenderSettings {
+        sampler: read_variant(&mut input)?,
+        seed: read_u64(&mut input)?,
+        budget: SampleBudget {
+            min_samples: read_u32(&mut input)?,
+            max_samples: read_u32(&mut input)?,
+            threshold: read_f64(&mut input)?,
+        },
+        filter: read_variant(&mut input)?,
+        filter_radius: read_f64(&mut input)?,
+        spectral: read_bool(&mut input)?,
+        illuminant: read_illuminant(&mut input)?,
+    };
+
+    let mut accumulation = Accumulation::new(width, height);
+    for pixel in accumulation.film.pixels_mut() {
+        pixel.color = Color::new(
+            read_f64(&mut input)?,
+            read_f64(&mut input)?,
+            read_f64(&mut input)?,
+        );
+        pixel.weight = read_f64(&mut input)?;
+    }
+    for statistics in accumulation.statistics.iter_mut() {
+        *statistics = PixelStatistics::from_parts(
+            read_u32(&mut input)?,
+            read_f64(&mut input)?,
+            read_f64(&mut input)?,
+        );
+    }
+
+    Ok((settings, accumulation))
+}
+
+/// FNV-1a hash of a description of the scene, stable between runs and builds
+pub fn fingerprint(description: &str) -> u64 {
+    description
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+fn invalid_data(message: &str) -> Error {
+    Error::InvalidCheckpoint(message.to_string())
+}
+
+/// stores a command line enum by its position among the variants
+fn write_variant<T: ValueEnum + PartialEq>(out: &mut impl Write, value: T) -> io::Result<()> {
+    let index = T::value_variants()
+        .iter()
+        .position(|variant| *variant == value)
+        .unwrap_or_default();
+    out.write_all(&[index as u8])
+}
+
+fn read_variant<T: ValueEnum + Clone>(input: &mut impl Read) -> Result<T> {
+    let mut index = [0; 1];
+    input.read_exact(&mut index)?;
+    T::value_variants()
+        .get(index[0] as usize)
+        .cloned()
+        .ok_or_else(|| invalid_data("unknown variant"))
+}
+
+/// the kind of illuminant followed by the temperature of a black body, 0 for the others
+fn write_illuminant(out: &mut impl Write, illuminant: Illuminant) -> io::Result<()> {
+    let (kind, temperature) = match illuminant {
+        Illuminant::D65 => (0, 0.),
+        Illuminant::A => (1, 0.),
+        Illuminant::E => (2, 0.),
+        Illuminant::Blackbody(temperature) => (3, temperature),
+    };
+    out.write_all(&[kind])?;
+    write_f64(out, temperature)
+}
+
+fn read_illuminant(input: &mut impl Read) -> Result<Illuminant> {
+    let mut kind = [0; 1];
+    input.read_exact(&mut kind)?;
+    let temperature = read_f64(input)?;
+    match kind[0] {
+        0 => Ok(Illuminant::D65),
+        1 => Ok(Illuminant::A),
+        2 => Ok(Illuminant::E),
+        3 => Illuminant::blackbody(temperature)
+            .ok_or_else(|| invalid_data("black body temperature out of range")),
+        _ => Err(invalid_data("unknown illuminant")),
+    }
+}
+
+fn write_bool(out: &mut impl Write, value: bool) -> io::Result<()> {
+    out.write_all(&[value as u8])
+}
+
+fn write_i32(out: &mut impl Write, value: i32) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn write_u32(out: &mut impl Write, value: u32) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn write_u64(out: &mut impl Write, value: u64) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn write_f64(out: &mut impl Write, value: f64) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn read_bool(input: &mut impl Read) -> io::Result<bool> {
+    let mut byte = [0; 1];
+    input.read_exact(&mut byte)?;
+    Ok(byte[0] != 0)
+}
+
+fn read_i32(input: &mut impl Read) -> io::Result<i32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(i32::from_le_bytes(bytes))
+}
+
+fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f64(input: &mut impl Read) -> io::Result<f64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(f64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::FilterKind;
+    use crate::sampler::SamplerKind;
+
+    #[test]
+    fn test_save_load() {
+        let settings = RenderSettings {
+            sampler: SamplerKind::Halton,
+            seed: 42,
+            budget: SampleBudget::adaptive(8, 64, 0.05),
+            filter: FilterKind::Mitchell,
+            filter_radius: 2.,
+            spectral: true,
+            illuminant: Illuminant::Blackbody(3200.),
+        };
+        let mut accumulation = Accumulation::new(30, 20);
+        accumulation.film.pixels_mut()[7].color = Color::new(1., 2., 3.);
+        accumulation.film.pixels_mut()[7].weight = 0.5;
+        accumulation.statistics[7].add(Color::new(0.25, 0.25, 0.25));
+
+        let path = std::env::temp_dir().join("ray-tracing-checkpoint-test");
+        let path = path.to_str().unwrap();
+        let area = Tile::image(30, 20);
+        let scene = fingerprint("scene");
+        save(path, &area, scene, &settings, &accumulation).unwrap();
+        let (loaded_settings, loaded) = load(path, 30, 20, &area, scene).unwrap();
+        let resized = load(path, 40, 20, &area, scene);
+        let cropped = load(path, 30, 20, &Tile::image(10, 20), scene);
+        let changed = load(path, 30, 20, &area, fingerprint("other scene"));
+        fs::remove_file(path).unwrap();
+        assert!(matches!(resized, Err(Error::InvalidCheckpoint(_))));
+        assert!(matches!(cropped, Err(Error::InvalidCheckpoint(_))));
+        assert!(matches!(changed, Err(Error::InvalidCheckpoint(_))));
+
+        assert_eq!(loaded_settings.sampler, SamplerKind::Halton);
+        assert_eq!(loaded_settings.seed, 42);
+        assert_eq!(loaded_settings.budget.max_samples, 64);
+        assert_eq!(loaded_settings.filter, FilterKind::Mitchell);
+        assert!(loaded_settings.spectral);
+        assert_eq!(loaded_settings.illuminant, Illuminant::Blackbody(3200.));
+        assert_eq!(loaded.film.pixels()[7].color, Color::new(1., 2., 3.));
+        assert_eq!(loaded.film.pixels()[7].weight, 0.5);
+        assert_eq!(loaded.statistics[7].count(), 1);
+        assert_eq!(loaded.statistics[7].mean(), 0.25);
+        let pixel = Tile {
+            x0: 7,
+            y0: 0,
+            width: 1,
+            height: 1,
+        };
+        assert_eq!(loaded.min_samples(&pixel), 1);
+        assert_eq!(loaded.min_samples(&Tile::image(30, 20)), 0);
+    }
+}
@@ -1,7 +1,283 @@
+use std::time::Duration;
+
+use ray_tracing_one_weekend::animation::{FrameRange, Interpolation};
+use ray_tracing_one_weekend::camera::{FisheyeMapping, Projection};
+use ray_tracing_one_weekend::filter::FilterKind;
+use ray_tracing_one_weekend::materials::ConductorPreset;
+use ray_tracing_one_weekend::sampler::SamplerKind;
+use ray_tracing_one_weekend::scheduler::{CropOutput, CropWindow, TileOrder};
+use ray_tracing_one_weekend::spectrum::{DispersionPreset, Illuminant};
+use ray_tracing_one_weekend::stereo::StereoLayout;
+use ray_tracing_one_weekend::utils;
+use ray_tracing_one_weekend::vec3::Color;
+
 #[derive(clap::Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+// options that make the render spectral
+#[command(group = clap::ArgGroup::new("spectral_render").multiple(true).args(["spectral", "glass_dispersion", "glass_abbe"]))]
 pub struct Args {
     /// No multithreading
     #[arg(short, long, default_value_t = false)]
     pub single_thread: bool,
+
+    /// Only print errors, no progress or statistics
+    #[arg(short, long, default_value_t = false)]
+    pub quiet: bool,
+
+    /// How random numbers for pixel, lens and bounce sampling are generated
+    #[arg(long, value_enum, default_value_t = SamplerKind::Sobol)]
+    pub sampler: SamplerKind,
+
+    /// Seed of the scene and of every sample, the output is identical for the same seed
+    /// regardless of the thread count. Random if not set
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Samples per pixel, the upper limit with adaptive sampling. Defaults to
+    /// 500, to no limit with --time-limit or, when resuming, to the value stored
+    /// in the checkpoint. A time limited checkpoint resumed without a limit stops
+    /// at 500
+    #[arg(long)]
+    pub max_samples: Option<u32>,
+
+    /// Enables adaptive sampling: a pixel stops taking samples once the 95% confidence
+    /// interval of its luminance is within this fraction of the mean, e.g. 0.05
+    #[arg(long)]
+    pub adaptive_threshold: Option<f64>,
+
+    /// Samples every pixel takes before adaptive sampling may stop it
+    #[arg(long, default_value_t = 16)]
+    pub min_samples: u32,
+
+    /// Writes a ppm image of the samples every pixel took
+    #[arg(long)]
+    pub heatmap: Option<String>,
+
+    /// Reconstruction filter weighting samples into the pixels around them
+    #[arg(long, value_enum, default_value_t = FilterKind::Box)]
+    pub filter: FilterKind,
+
+    /// Radius of the filter in pixels, every filter has its own default
+    #[arg(long, value_parser = utils::parse_positive)]
+    pub filter_radius: Option<f64>,
+
+    /// Renders the image in passes of this many samples per pixel, writing the
+    /// output file after every pass
+    #[arg(long, requires = "output")]
+    pub progressive: Option<u32>,
+
+    /// Writes the image to this ppm file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// Periodically saves the accumulated image and sample statistics to this file
+    #[arg(long)]
+    pub checkpoint: Option<String>,
+
+    /// Seconds between two checkpoints, one is also saved after every pass
+    #[arg(long, default_value_t = 60)]
+    pub checkpoint_interval: u64,
+
+    /// Continues adding samples to a checkpoint, the settings stored in it are used
+    /// instead of the sampling and filter options
+    #[arg(long)]
+    pub resume: Option<String>,
+
+    /// Keeps adding passes of samples until this much time has passed, e.g. 90s,
+    /// 10m or 1h30m. The render stops with the first tile finished after that
+    #[arg(long, value_parser = utils::parse_duration)]
+    pub time_limit: Option<Duration>,
+
+    /// Side of the square tiles the image is split into for rendering
+    #[arg(long, default_value_t = 32)]
+    pub tile_size: i32,
+
+    /// Order in which tiles are rendered
+    #[arg(long, value_enum, default_value_t = TileOrder::Spiral)]
+    pub tile_order: TileOrder,
+
+    /// How the camera projects the scene onto the image
+    #[arg(long, value_enum, default_value_t = Projection::Perspective)]
+    pub projection: Projection,
+
+    /// Width of the scene seen by the orthographic camera. Defaults to what the
+    /// perspective camera sees at its focus distance
+    #[arg(long, value_parser = utils::parse_positive)]
+    pub view_width: Option<f64>,
+
+    /// Focuses on what is seen through the pixel x,y, counted from the upper left corner
+    #[arg(long, value_name = "X,Y", value_parser = utils::parse_pixel)]
+    pub focus_pixel: Option<(u32, u32)>,
+
+    /// Focuses on the named object of the scene: ground, glass, matte or metal
+    #[arg(long, conflicts_with = "focus_pixel")]
+    pub focus_on: Option<String>,
+
+    /// Focal length in mm, enables the physical camera: field of view and aperture come
+    /// from the lens and sensor, and the image is exposed from f-number, shutter and ISO
+    /// with scene units in meters. The default f/2.8, 1/60 and ISO 100 keep the
+    /// brightness of a render without a physical camera
+    #[arg(long, value_parser = utils::parse_positive)]
+    pub focal_length: Option<f64>,
+
+    /// Sensor width in mm of the physical camera
+    #[arg(long, default_value_t = 36., requires = "focal_length", value_parser = utils::parse_positive)]
+    pub sensor_width: f64,
+
+    /// Sensor height in mm of the physical camera
+    #[arg(long, default_value_t = 24., requires = "focal_length", value_parser = utils::parse_positive)]
+    pub sensor_height: f64,
+
+    /// Relative aperture of the physical camera
+    #[arg(long, default_value_t = 2.8, requires = "focal_length", value_parser = utils::parse_positive)]
+    pub f_number: f64,
+
+    /// Exposure time of the physical camera in seconds, e.g. 1/125
+    #[arg(long, default_value = "1/60", value_parser = utils::parse_shutter, requires = "focal_length")]
+    pub shutter: f64,
+
+    /// Sensitivity of the physical camera
+    #[arg(long, default_value_t = 100., requires = "focal_length", value_parser = utils::parse_positive)]
+    pub iso: f64,
+
+    /// Number of blades forming a polygonal aperture, a round one if not set
+    #[arg(long)]
+    pub aperture_blades: Option<u32>,
+
+    /// Rotation of the polygonal aperture in degrees
+    #[arg(long, default_value_t = 0.)]
+    pub aperture_rotation: f64,
+
+    /// Grayscale ppm image of the aperture, brighter pixels let through more light
+    #[arg(long, conflicts_with = "aperture_blades")]
+    pub aperture_mask: Option<String>,
+
+    /// Squeeze factor of an anamorphic lens, out-of-focus highlights get that many
+    /// times taller than wide
+    #[arg(long, default_value_t = 1., value_parser = utils::parse_positive)]
+    pub anamorphic_squeeze: f64,
+
+    /// Optical vignetting: how far the lens barrel cuts into the aperture at the edge
+    /// of the image, in aperture radii. Gives cat-eye shaped highlights off center
+    #[arg(long, default_value_t = 0.)]
+    pub optical_vignetting: f64,
+
+    /// How the fisheye camera maps angles from its axis to the image circle
+    #[arg(long, value_enum, default_value_t = FisheyeMapping::Equidistant)]
+    pub fisheye_mapping: FisheyeMapping,
+
+    /// Field of view of the fisheye camera across its image circle in degrees
+    #[arg(long, default_value_t = 180.)]
+    pub fov: f64,
+
+    /// Renders a stereo pair for both eyes into one image laid out like this,
+    /// omni-directional stereo with the equirectangular projection
+    #[arg(long, value_enum)]
+    pub stereo: Option<StereoLayout>,
+
+    /// Distance between the eyes of a stereo pair in scene units
+    #[arg(long, default_value_t = 0.064, requires = "stereo")]
+    pub ipd: f64,
+
+    /// Distance at which the eyes of a stereo pair converge, objects there have no
+    /// parallax. Defaults to the focus distance, inf gives parallel eyes
+    #[arg(long, requires = "stereo", value_parser = utils::parse_distance)]
+    pub convergence: Option<f64>,
+
+    /// Only renders the window x0,y0,x1,y1 of the frame, in pixels or, written with a
+    /// decimal point, in fractions of the image size (e.g. 0.4,0.2,0.6,0.5)
+    #[arg(long)]
+    pub crop: Option<CropWindow>,
+
+    /// Whether a crop window is written on its own or in a full frame with the rest black
+    #[arg(long, value_enum, default_value_t = CropOutput::Cropped, requires = "crop")]
+    pub crop_output: CropOutput,
+
+    /// Renders an image sequence of these frames, e.g. 0..120 or 1..=24, moving the
+    /// camera along --camera-path. A run of # in --output is replaced by the frame
+    /// number padded with zeros, without one the number goes before the extension
+    #[arg(long, requires = "output", conflicts_with_all = ["resume", "checkpoint", "heatmap"])]
+    pub frames: Option<FrameRange>,
+
+    /// File of camera keyframes, one per line with the frame, the camera position,
+    /// the point looked at, the vertical field of view and the focus distance:
+    /// `frame  x y z  x y z  vfov  focus`. Lines starting with # are comments.
+    /// Without --frames the image is rendered at frame 0
+    #[arg(long)]
+    pub camera_path: Option<String>,
+
+    /// Makes the large metal sphere a rough GGX conductor of this metal instead of the
+    /// fuzzy mirror
+    #[arg(long, value_enum)]
+    pub conductor: Option<ConductorPreset>,
+
+    /// Roughness of the GGX conductor, 0 is a mirror
+    #[arg(long, default_value_t = 0.3, requires = "conductor")]
+    pub roughness: f64,
+
+    /// Stretches the highlights of the GGX conductor around the vertical axis when
+    /// positive and along it when negative, from -1 to 1
+    #[arg(
+        long,
+        default_value_t = 0.,
+        allow_negative_numbers = true,
+        requires = "conductor"
+    )]
+    pub anisotropy: f64,
+
+    /// Index of refraction of the large glass sphere, e.g. 1.31 for ice
+    #[arg(long, default_value_t = 1.5, value_parser = utils::parse_positive)]
+    pub glass_ior: f64,
+
+    /// Makes the large glass sphere frosted, rough GGX glass with this roughness
+    #[arg(long)]
+    pub glass_roughness: Option<f64>,
+
+    /// Tints the large glass sphere: the fraction of red, green and blue light left
+    /// after --glass-absorption-distance inside it, e.g. 0.6,0.9,0.7
+    #[arg(long, value_name = "R,G,B", value_parser = utils::parse_color)]
+    pub glass_color: Option<Color>,
+
+    /// Distance inside the glass at which --glass-color is reached, thicker glass is
+    /// darker
+    #[arg(long, default_value_t = 1., requires = "glass_color", value_parser = utils::parse_positive)]
+    pub glass_absorption_distance: f64,
+
+    /// Makes the large glass sphere split light into a rainbow like this material,
+    /// its index of refraction then replaces --glass-ior. Turns on --spectral
+    #[arg(long, value_enum, conflicts_with_all = ["glass_abbe", "glass_roughness"])]
+    pub glass_dispersion: Option<DispersionPreset>,
+
+    /// Makes the large glass sphere dispersive with this Abbe number around
+    /// --glass-ior, lower numbers spread the colors wider, e.g. 64 for crown glass
+    /// and 20 for dense flint. Turns on --spectral
+    #[arg(long, conflicts_with = "glass_roughness", value_parser = utils::parse_positive)]
+    pub glass_abbe: Option<f64>,
+
+    /// Moves the named object (ground, glass, matte or metal) along the keyframes in
+    /// FILE, one per line: `frame  tx ty tz  rx ry rz  sx sy sz` with the translation,
+    /// the rotation around x, y and z in degrees and the scale above zero. Can be
+    /// repeated for different objects
+    #[arg(long, value_name = "NAME=FILE", value_parser = utils::parse_assignment)]
+    pub animate: Vec<(String, String)>,
+
+    /// How the camera and animated objects move between keyframes
+    #[arg(long, value_enum, default_value_t = Interpolation::CatmullRom)]
+    pub interpolation: Interpolation,
+
+    /// When the shutter opens and closes relative to the frame, in frames, e.g. 0,0.5
+    /// for half a frame. Moving objects are blurred over that time
+    #[arg(long, value_name = "OPEN,CLOSE", default_value = "0,0", value_parser = utils::parse_interval)]
+    pub shutter_interval: (f64, f64),
+
+    /// Traces wavelengths of light instead of red, green and blue, colors are turned
+    /// into smooth spectra. Slower, but light mixes and filters like it does in nature
+    #[arg(long)]
+    pub spectral: bool,
+
+    /// Light of the sky in spectral renders: d65 for daylight, a for incandescent
+    /// light, e for equal energy or a black body temperature like 3200K. Colors stay
+    /// balanced for daylight, so other light tints the image
+    #[arg(long, default_value = "d65", requires = "spectral_render")]
+    pub illuminant: Illuminant,
 }
@@ -0,0 +1,64 @@
+use std::cell::Cell;
+use std::ops;
+
+/// Work done while tracing, counted per thread and summed up per tile.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RayCounts {
+    /// rays leaving the camera, one per sample
+    pub primary_rays: u64,
+    /// every ray tested against the world, primary ones included
+    pub rays: u64,
+    /// ray against object tests
+    pub intersection_tests: u64,
+}
+
+impl RayCounts {
+    pub fn secondary_rays(&self) -> u64 {
+        self.rays - self.primary_rays
+    }
+
+    /// average number of rays in a path from the camera
+    pub fn average_path_length(&self) -> f64 {
+        if self.primary_rays == 0 {
+            return 0.;
+        }
+        self.rays as f64 / self.primary_rays as f64
+    }
+}
+
+impl ops::AddAssign for RayCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.primary_rays += other.primary_rays;
+        self.rays += other.rays;
+        self.intersection_tests += other.intersection_tests;
+    }
+}
+
+thread_local! {
+    static COUNTS: Cell<RayCounts> = Cell::new(RayCounts::default());
+}
+
+fn update(change: impl FnOnce(&mut RayCounts)) {
+    COUNTS.with(|counts| {
+        let mut value = counts.get();
+        change(&mut value);
+        counts.set(value);
+    });
+}
+
+pub fn count_primary_ray() {
+    update(|counts| counts.primary_rays += 1);
+}
+
+pub fn count_ray() {
+    update(|counts| counts.rays += 1);
+}
+
+pub fn count_intersection_tests(tests: usize) {
+    update(|counts| counts.intersection_tests += tests as u64);
+}
+
+/// returns what the current thread counted since the last call
+pub fn take() -> RayCounts {
+    COUNTS.with(|counts| counts.replace(RayCounts::default()))
+}
@@ -0,0 +1,70 @@
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// the file is not a checkpoint of this image
+    InvalidCheckpoint(String),
+    /// an image read as input could not be understood
+    InvalidImage(String),
+    /// a file of keyframes could not be understood
+    InvalidKeyframes(String),
+    /// no object of the world has this name
+    UnknownObject(String),
+    /// a pixel given as input lies outside of the `width` x `height` image
+    PixelOutsideImage {
+        x: u32,
+        y: u32,
+        width: i32,
+        height: i32,
+    },
+    /// the named object was given a second path to move along
+    AlreadyAnimated(String),
+    /// a render thread panicked, the tiles it was working on are missing
+    WorkerPanicked,
+    /// the render was stopped through its cancellation token
+    Cancelled,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "{}", error),
+            Error::InvalidCheckpoint(message) => write!(f, "invalid checkpoint: {}", message),
+            Error::InvalidImage(message) => write!(f, "invalid image: {}", message),
+            Error::InvalidKeyframes(message) => write!(f, "invalid keyframes: {}", message),
+            Error::UnknownObject(name) => write!(f, "no object named '{}'", name),
+            Error::PixelOutsideImage {
+                x,
+                y,
+                width,
+                height,
+            } => write!(
+                f,
+                "pixel {},{} is outside of the {}x{} image",
+                x, y, width, height
+            ),
+            Error::AlreadyAnimated(name) => write!(f, "object '{}' is already animated", name),
+            Error::WorkerPanicked => write!(f, "a render thread panicked"),
+            Error::Cancelled => write!(f, "render cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
@@ -0,0 +1,224 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+
+use crate::filter::Filter;
+use crate::scheduler::Tile;
+use crate::vec3::{Color, Vec3};
+
+const BRIGHTNESS: i32 = 255;
+
+#[derive(Clone, Copy)]
+pub struct Pixel {
+    /// sum of the filter weighted samples splatted into the pixel
+    pub color: Color,
+    pub weight: f64,
+}
+
+impl Pixel {
+    fn empty() -> Self {
+        Self {
+            color: Color::origin(),
+            weight: 0.,
+        }
+    }
+}
+
+/// Linear rgb colors of a rendered image.
+pub struct Image {
+    pub width: i32,
+    pub height: i32,
+    /// from the upper left corner to the lower right one
+    pub pixels: Vec<Color>,
+}
+
+impl Image {
+    pub fn pixel(&self, col: i32, row: i32) -> Color {
+        self.pixels[(row * self.width + col) as usize]
+    }
+}
+
+/// Accumulates filtered samples for a rectangle of the image.
+///
+/// Coordinates are in pixels of the whole image with y going from top to bottom,
+/// the center of pixel (col, row) is (col + 0.5, row + 0.5).
+pub struct Film {
+    x0: i32,
+    y0: i32,
+    width: i32,
+    height: i32,
+    pixels: Vec<Pixel>,
+}
+
+impl Film {
+    pub fn new(width: i32, height: i32) -> Self {
+        Self::region(0, 0, width, height)
+    }
+
+    /// film for part of the image with its upper left pixel at (x0, y0)
+    pub fn region(x0: i32, y0: i32, width: i32, height: i32) -> Self {
+        Self {
+            x0,
+            y0,
+            width,
+            height,
+            pixels: vec![Pixel::empty(); (width * height) as usize],
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    fn index(&self, col: i32, row: i32) -> Option<usize> {
+        let x = col - self.x0;
+        let y = row - self.y0;
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return None;
+        }
+        Some((y * self.width + x) as usize)
+    }
+
+    /// pixels from the upper left corner to the lower right one
+    pub fn pixels(&self) -> &[Pixel] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [Pixel] {
+        &mut self.pixels
+    }
+
+    /// splats a sample taken at (x, y) into every pixel the filter reaches
+    pub fn add_sample(&mut self, x: f64, y: f64, color: Color, filter: &dyn Filter) {
+        let radius = filter.radius();
+        let col_start = ((x - 0.5 - radius).ceil() as i32).max(self.x0);
+        let col_end = ((x - 0.5 + radius).floor() as i32).min(self.x0 + self.width - 1);
+        let row_start = ((y - 0.5 - radius).ceil() as i32).max(self.y0);
+        let row_end = ((y - 0.5 + radius).floor() as i32).min(self.y0 + self.height - 1);
+
+        for row in row_start..=row_end {
+            for col in col_start..=col_end {
+                let weight = filter.evaluate(col as f64 + 0.5 - x, row as f64 + 0.5 - y);
+                if weight == 0. {
+                    continue;
+                }
+                if let Some(index) = self.index(col, row) {
+                    let pixel = &mut self.pixels[index];
+                    pixel.color += weight * color;
+                    pixel.weight += weight;
+                }
+            }
+        }
+    }
+
+    /// multiplies every pixel, e.g. by the exposure of the camera
+    pub fn scale(&mut self, factor: f64) {
+        for pixel in self.pixels.iter_mut() {
+            pixel.color *= factor;
+        }
+    }
+
+    /// copy of the pixels inside the rectangle, dropping the filter tails outside of it
+    pub fn cropped(&self, area: &Tile) -> Film {
+        let mut film = Film::region(area.x0, area.y0, area.width, area.height);
+        film.merge(self);
+        film
+    }
+
+    /// the filtered color of every pixel
+    pub fn image(&self) -> Image {
+        let pixels = self
+            .pixels
+            .iter()
+            .map(|pixel| {
+                if pixel.weight > 0. {
+                    pixel.color / pixel.weight
+                } else {
+                    Color::origin()
+                }
+            })
+            .collect();
+        Image {
+            width: self.width,
+            height: self.height,
+            pixels,
+        }
+    }
+
+    /// adds the overlapping part of another film to this one
+    pub fn merge(&mut self, other: &Film) {
+        for y in 0..other.height {
+            for x in 0..other.width {
+                let col = other.x0 + x;
+                let row = other.y0 + y;
+                if let Some(index) = self.index(col, row) {
+                    let source = other.pixels[(y * other.width + x) as usize];
+                    let pixel = &mut self.pixels[index];
+                    pixel.color += source.color;
+                    pixel.weight += source.weight;
+                }
+            }
+        }
+    }
+
+    pub fn write_ppm(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "P3\n{} {}\n{}", self.width, self.height, BRIGHTNESS)?;
+        for row in self.pixels.chunks(self.width as usize) {
+            for pixel in row {
+                Vec3::write_color(out, pixel.color, pixel.weight)?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    /// writes the image next to `path` first, so the file is never seen half written
+    pub fn save_ppm(&self, path: &str) -> io::Result<()> {
+        let temporary = format!("{}.tmp", path);
+        let mut file = BufWriter::new(File::create(&temporary)?);
+        self.write_ppm(&mut file)?;
+        file.flush()?;
+        drop(file);
+        fs::rename(&temporary, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::FilterKind;
+
+    #[test]
+    fn test_box_filter_keeps_sample_in_its_pixel() {
+        let filter = FilterKind::Box.create(0.5);
+        let mut film = Film::new(3, 3);
+        film.add_sample(1.3, 1.7, Color::new(1., 2., 3.), filter.as_ref());
+        for (index, pixel) in film.pixels.iter().enumerate() {
+            if index == 4 {
+                assert_eq!(pixel.color, Color::new(1., 2., 3.));
+                assert_eq!(pixel.weight, 1.);
+            } else {
+                assert_eq!(pixel.weight, 0.);
+            }
+        }
+    }
+
+    #[test]
+    fn test_merge_region() {
+        let filter = FilterKind::Tent.create(1.);
+        let mut film = Film::new(4, 4);
+        let mut region = Film::region(0, 1, 4, 2);
+        region.add_sample(2., 2., Color::new(1., 1., 1.), filter.as_ref());
+        film.merge(&region);
+
+        let pixels = &film.pixels;
+        // the sample sits on the corner shared by four pixels
+        for index in [4 + 1, 4 + 2, 8 + 1, 8 + 2] {
+            assert_eq!(pixels[index].weight, 0.25);
+        }
+        assert_eq!(pixels[0].weight, 0.);
+    }
+}
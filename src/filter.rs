@@ -0,0 +1,237 @@
+use std::f64::consts::PI;
+
+/// Weights a sample by its offset from the center of the pixel it is splatted into.
+pub trait Filter {
+    /// samples further than this from a pixel center along x or y do not contribute to it
+    fn radius(&self) -> f64;
+    fn evaluate(&self, x: f64, y: f64) -> f64;
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterKind {
+    /// Every sample within the radius weights the same
+    Box,
+    /// Weight falls linearly to zero at the radius
+    Tent,
+    /// Gaussian with the standard deviation of a third of the radius
+    Gaussian,
+    /// Mitchell-Netravali cubic with B = C = 1/3
+    Mitchell,
+    /// Sinc windowed by a sinc as wide as the radius
+    Lanczos,
+}
+
+impl FilterKind {
+    pub fn default_radius(self) -> f64 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.,
+            FilterKind::Lanczos => 3.,
+        }
+    }
+
+    pub fn create(self, radius: f64) -> Box<dyn Filter> {
+        match self {
+            FilterKind::Box => Box::new(BoxFilter { radius }),
+            FilterKind::Tent => Box::new(TentFilter { radius }),
+            FilterKind::Gaussian => Box::new(GaussianFilter::new(radius)),
+            FilterKind::Mitchell => Box::new(MitchellFilter::new(radius, 1. / 3., 1. / 3.)),
+            FilterKind::Lanczos => Box::new(LanczosFilter { radius }),
+        }
+    }
+}
+
+pub struct BoxFilter {
+    radius: f64,
+}
+
+impl Filter for BoxFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        // half open, so a sample on the edge between two pixels counts in only one
+        let inside = |offset: f64| -self.radius < offset && offset <= self.radius;
+        if inside(x) && inside(y) {
+            1.
+        } else {
+            0.
+        }
+    }
+}
+
+pub struct TentFilter {
+    radius: f64,
+}
+
+impl Filter for TentFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        (self.radius - x.abs()).max(0.) * (self.radius - y.abs()).max(0.)
+    }
+}
+
+pub struct GaussianFilter {
+    radius: f64,
+    sigma: f64,
+    // value at the radius, subtracted so the filter goes smoothly to zero
+    edge: f64,
+}
+
+impl GaussianFilter {
+    pub fn new(radius: f64) -> Self {
+        let sigma = radius / 3.;
+        Self {
+            radius,
+            sigma,
+            edge: gaussian(radius, sigma),
+        }
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        (gaussian(x, self.sigma) - self.edge).max(0.)
+    }
+}
+
+impl Filter for GaussianFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+}
+
+fn gaussian(x: f64, sigma: f64) -> f64 {
+    (-x * x / (2. * sigma * sigma)).exp() / (2. * PI * sigma * sigma).sqrt()
+}
+
+pub struct MitchellFilter {
+    radius: f64,
+    b: f64,
+    c: f64,
+}
+
+impl MitchellFilter {
+    pub fn new(radius: f64, b: f64, c: f64) -> Self {
+        Self { radius, b, c }
+    }
+
+    /// the cubic is defined on [-2, 2], `x` is scaled from [-radius, radius]
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        let x = (2. * x / self.radius).abs();
+        let (b, c) = (self.b, self.c);
+        let value = if x > 2. {
+            0.
+        } else if x > 1. {
+            (-b - 6. * c) * x.powi(3)
+                + (6. * b + 30. * c) * x.powi(2)
+                + (-12. * b - 48. * c) * x
+                + (8. * b + 24. * c)
+        } else {
+            (12. - 9. * b - 6. * c) * x.powi(3)
+                + (-18. + 12. * b + 6. * c) * x.powi(2)
+                + (6. - 2. * b)
+        };
+        value / 6.
+    }
+}
+
+impl Filter for MitchellFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+}
+
+pub struct LanczosFilter {
+    radius: f64,
+}
+
+impl LanczosFilter {
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        if x.abs() > self.radius {
+            return 0.;
+        }
+        sinc(x) * sinc(x / self.radius)
+    }
+}
+
+impl Filter for LanczosFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        return 1.;
+    }
+    (PI * x).sin() / (PI * x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::film::Film;
+    use crate::utils::compare_floats;
+    use crate::vec3::Color;
+
+    #[test]
+    fn test_filters_vanish_at_radius() {
+        for kind in [
+            FilterKind::Tent,
+            FilterKind::Gaussian,
+            FilterKind::Mitchell,
+            FilterKind::Lanczos,
+        ] {
+            let filter = kind.create(kind.default_radius());
+            let radius = filter.radius();
+            assert!(filter.evaluate(0., 0.) > 0., "{:?}", kind);
+            assert!(
+                compare_floats(filter.evaluate(radius, 0.), 0.),
+                "{:?}",
+                kind
+            );
+            assert!(
+                compare_floats(filter.evaluate(0., -radius), 0.),
+                "{:?}",
+                kind
+            );
+            assert_eq!(filter.evaluate(radius + 0.1, 0.), 0., "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn test_box_filter_edges() {
+        let filter = FilterKind::Box.create(0.5);
+        assert_eq!(filter.evaluate(0.5, 0.), 1.);
+        assert_eq!(filter.evaluate(-0.5, 0.), 0.);
+        assert_eq!(filter.evaluate(0., -0.5), 0.);
+        // a sample on the edge between two pixels lands in exactly one of them
+        let mut film = Film::region(0, 0, 2, 1);
+        film.add_sample(1., 0.5, Color::new(1., 1., 1.), filter.as_ref());
+        let weights: Vec<f64> = film.pixels().iter().map(|pixel| pixel.weight).collect();
+        assert_eq!(weights.iter().sum::<f64>(), 1.);
+    }
+
+    #[test]
+    fn test_mitchell_negative_lobe() {
+        let filter = FilterKind::Mitchell.create(2.);
+        assert!(filter.evaluate(1.5, 0.) < 0.);
+    }
+}
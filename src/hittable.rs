@@ -3,7 +3,12 @@ use crate::ray::Ray;
 use crate::vec3::{Point3, Vec3};
 
 pub trait Hittable {
-    fn hit(&self, ray: &Ray, min: f64, max: f64) -> Option<HitRecord>;
+    fn hit(&self, ray: &Ray, min: f64, max: f64) -> Option<HitRecord<'_>>;
+
+    /// point inside the object at `time` to aim at, e.g. to focus on it
+    fn center(&self, _time: f64) -> Option<Point3> {
+        None
+    }
 }
 
 pub struct HitRecord<'m> {
@@ -0,0 +1,32 @@
+//! Path tracer from "Ray Tracing in One Weekend".
+//!
+//! A scene is a [`world::World`] of [`hittable::Hittable`] objects seen through a
+//! [`camera::Camera`]. [`render`] traces it tile by tile into an [`render::Accumulation`],
+//! whose [`film::Film`] is written out as a ppm image. [`render::render_image`] does
+//! all of it in one call and returns the pixels in memory.
+
+pub mod adaptive;
+pub mod animation;
+pub mod aperture;
+pub mod camera;
+pub mod checkpoint;
+pub mod counters;
+pub mod error;
+pub mod film;
+pub mod filter;
+pub mod hittable;
+pub mod materials;
+pub mod microfacet;
+pub mod ray;
+pub mod render;
+pub mod sampler;
+pub mod scheduler;
+pub mod spectrum;
+pub mod sphere;
+pub mod stereo;
+pub mod transform;
+pub mod utils;
+pub mod vec3;
+pub mod world;
+
+pub use error::{Error, Result};
@@ -1,220 +1,638 @@
-use std::sync::mpsc;
-use std::sync::{Arc, Mutex};
-use std::thread;
-use std::thread::JoinHandle;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::process;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use clap::Parser;
 use rand::{thread_rng, Rng};
 
-use crate::vec3::{Color, Point3, Vec3};
-use clap::Parser;
-use std::cmp::Ordering;
-use std::collections::BinaryHeap;
-use std::time::Instant;
+use ray_tracing_one_weekend::adaptive::{self, SampleBudget};
+use ray_tracing_one_weekend::animation::{self, CameraPath, CameraPose, TransformPath};
+use ray_tracing_one_weekend::aperture::{Aperture, ApertureMask};
+use ray_tracing_one_weekend::camera::{
+    self, Camera, EquirectangularCamera, FisheyeCamera, Frame, OrthographicCamera, PhysicalCamera,
+    Projection, ShutterCamera, ThinLensCamera,
+};
+use ray_tracing_one_weekend::checkpoint;
+use ray_tracing_one_weekend::film::Film;
+use ray_tracing_one_weekend::hittable::Hittable;
+use ray_tracing_one_weekend::materials::{
+    Absorption, Dielectric, GgxConductor, Material, RoughDielectric,
+};
+use ray_tracing_one_weekend::microfacet::Ggx;
+use ray_tracing_one_weekend::ray::Ray;
+use ray_tracing_one_weekend::render::{self, Accumulation, RenderSettings};
+use ray_tracing_one_weekend::sampler::SamplerKind;
+use ray_tracing_one_weekend::scheduler::{self, CropOutput, Tile};
+use ray_tracing_one_weekend::spectrum::Dispersion;
+use ray_tracing_one_weekend::stereo::StereoCamera;
+use ray_tracing_one_weekend::utils;
+use ray_tracing_one_weekend::vec3::{Point3, Vec3};
+use ray_tracing_one_weekend::world::{CoverMaterials, World};
+use ray_tracing_one_weekend::{Error, Result};
+
+use crate::progress::ProgressReporter;
 
-mod camera;
 mod cli;
-mod hittable;
-mod materials;
-mod ray;
-mod sphere;
-mod utils;
-mod vec3;
-mod world;
+mod progress;
 
 const ASPECT_RATIO: f64 = 3.0 / 2.0;
+/// aspect ratio of the views of equirectangular panoramas, 360 by 180 degrees
+const PANORAMA_ASPECT_RATIO: f64 = 2.;
 const IMAGE_WIDTH: i32 = 1200;
-const IMAGE_HEIGHT: i32 = (IMAGE_WIDTH as f64 / ASPECT_RATIO) as i32;
-const BRIGHTNESS: i32 = 255;
-const SAMPLES_PER_PIXEL: i32 = 500;
-const MAX_DEPTH: i32 = 50;
+const SAMPLES_PER_PIXEL: u32 = 500;
+const TIME_LIMIT_PASS_SAMPLES: u32 = render::UNLIMITED_STRATA;
+const DEFAULT_FOCUS_DISTANCE: f64 = 10.;
 
 fn main() {
-    let the_world = Arc::new(world::World::with_items());
+    let args = cli::Args::parse();
+    if let Err(error) = run(args) {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    }
+}
 
-    let lookfrom = Point3 {
-        x: 13.0,
-        y: 2.0,
-        z: 3.0,
+fn run(args: cli::Args) -> Result<()> {
+    let reporter = ProgressReporter::new(args.quiet);
+    let area = render_area(&args);
+    let (width, height) = image_size(&args);
+    let scene = scene_fingerprint(&args)?;
+
+    let (settings, mut accumulation) = match &args.resume {
+        Some(path) => {
+            let (mut settings, accumulation) = checkpoint::load(path, width, height, &area, scene)?;
+            match args.max_samples {
+                Some(max_samples) if max_samples < accumulation.min_samples(&area) => {
+                    return Err(Error::InvalidCheckpoint(format!(
+                        "checkpoint already has {} samples per pixel, more than --max-samples {}",
+                        accumulation.min_samples(&area),
+                        max_samples
+                    )));
+                }
+                Some(max_samples) => settings.budget.max_samples = max_samples,
+                // a time limited checkpoint only ends with a time limit
+                None if settings.budget.is_unlimited() && args.time_limit.is_none() => {
+                    settings.budget.max_samples = SAMPLES_PER_PIXEL
+                }
+                None => {}
+            }
+            reporter.message(&format!(
+                "resuming {} with {:.2} samples per pixel",
+                path,
+                accumulation.samples_per_pixel(&area)
+            ));
+            (settings, accumulation)
+        }
+        None => (settings_from_args(&args), Accumulation::new(width, height)),
     };
-    let lookat = Point3 {
-        x: 0.0,
-        y: 0.0,
-        z: 0.0,
+    reporter.message(&format!("seed {}", settings.seed));
+
+    let mut the_world = World::with_materials(settings.seed, cover_materials(&args));
+    for (name, path) in &args.animate {
+        the_world.animate(name, TransformPath::load(path, args.interpolation)?)?;
+    }
+    let the_world = Arc::new(the_world);
+    let camera_path = match &args.camera_path {
+        Some(path) => Some(CameraPath::load(path, args.interpolation)?),
+        None => None,
     };
-    let vup = Vec3 {
-        x: 0.0,
-        y: 1.0,
-        z: 0.0,
+    let pose_at = |frame: u32| match &camera_path {
+        Some(path) => path.at(frame as f64),
+        None => CameraPose {
+            lookfrom: Point3 {
+                x: 13.0,
+                y: 2.0,
+                z: 3.0,
+            },
+            lookat: Point3 {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            vfov: 20.,
+            focus_distance: DEFAULT_FOCUS_DISTANCE,
+        },
     };
-    let dist_to_focus = 10.;
-    let aperture = 0.1;
-    let camera = Arc::new(camera::Camera::new(
-        lookfrom,
-        lookat,
-        vup,
-        20.,
-        ASPECT_RATIO,
-        aperture,
-        dist_to_focus,
-    ));
-
-    let args = cli::Args::parse();
-
-    // header of ppm image file
-    println!("P3\n{} {}\n{}", IMAGE_WIDTH, IMAGE_HEIGHT, BRIGHTNESS);
 
     if args.single_thread {
-        eprintln!("use single thread");
-        single_thread(&camera, &the_world);
+        reporter.message("use single thread");
     } else {
-        eprintln!("use multiple threads");
-        multiple_threads(&camera, &the_world);
+        reporter.message(&format!("running on {} threads", render::thread_count()));
     }
-}
 
-fn multiple_threads(camera: &Arc<camera::Camera>, the_world: &Arc<world::World>) {
-    let thread_count = match ::num_cpus::get() {
-        0..=1 => 1,
-        n => n - 1,
+    let frames = match args.frames {
+        Some(frames) => frames,
+        None => {
+            let camera =
+                frame_camera(&args, &pose_at(0), 0., &the_world, settings.seed, &reporter)?;
+            render_frame(
+                &args,
+                &reporter,
+                settings,
+                &the_world,
+                &camera,
+                &mut accumulation,
+                args.output.as_deref(),
+            )?;
+            if let Some(path) = &args.heatmap {
+                let samples: Vec<u32> = accumulation
+                    .statistics
+                    .iter()
+                    .map(|statistics| statistics.count())
+                    .collect();
+                // with a time limit the budget may be unbounded, scale to what was reached
+                let max_samples = samples.iter().copied().max().unwrap_or_default();
+                adaptive::write_heatmap(path, width, height, &samples, max_samples)?;
+            }
+            return Ok(());
+        }
     };
-    eprintln!("running on {} threads", thread_count);
 
-    let mut threads: Vec<JoinHandle<()>> = Vec::with_capacity(thread_count + 1);
-    let (tx, rx) = mpsc::channel::<(usize, i32)>();
-    let rx = Arc::new(Mutex::new(rx));
+    // clap makes sure an output is given with --frames
+    let template = args.output.as_deref().unwrap_or_default();
+    for frame in frames.frames() {
+        let path = animation::frame_path(template, frame);
+        reporter.message(&format!(
+            "frame {} ({} of {}) to {}",
+            frame,
+            frame - frames.start + 1,
+            frames.len(),
+            path
+        ));
+        let camera = frame_camera(
+            &args,
+            &pose_at(frame),
+            frame as f64,
+            &the_world,
+            settings.seed,
+            &reporter,
+        )?;
+        let mut accumulation = Accumulation::new(width, height);
+        render_frame(
+            &args,
+            &reporter,
+            settings,
+            &the_world,
+            &camera,
+            &mut accumulation,
+            Some(&path),
+        )?;
+    }
+    Ok(())
+}
 
-    let (row_tx, row_rx) = mpsc::channel::<Row>();
+/// A camera set up from the options at one pose, and the exposure of its images.
+struct FrameCamera {
+    camera: Arc<dyn Camera>,
+    exposure: f64,
+}
 
-    threads.push(thread::spawn(move || {
-        for (enumerator, j) in (0..IMAGE_HEIGHT).rev().enumerate() {
-            tx.send((enumerator, j)).unwrap();
+fn frame_camera(
+    args: &cli::Args,
+    pose: &CameraPose,
+    time: f64,
+    the_world: &World,
+    seed: u64,
+    reporter: &ProgressReporter,
+) -> Result<FrameCamera> {
+    let CameraPose {
+        lookfrom, lookat, ..
+    } = *pose;
+    let vup = Vec3 {
+        x: 0.0,
+        y: 1.0,
+        z: 0.0,
+    };
+    // with stereo the camera frames the view of one eye
+    let aspect_ratio = match args.stereo {
+        Some(layout) => layout.eye_aspect_ratio(image_aspect_ratio(args)),
+        None => image_aspect_ratio(args),
+    };
+    let (vfov, aperture, exposure) = match args.focal_length {
+        Some(focal_length) => {
+            let physical = PhysicalCamera {
+                focal_length,
+                sensor_width: args.sensor_width,
+                sensor_height: args.sensor_height,
+                f_number: args.f_number,
+                shutter: args.shutter,
+                iso: args.iso,
+            };
+            (
+                physical.vfov(aspect_ratio),
+                physical.aperture(),
+                physical.exposure(),
+            )
         }
-    }));
-
-    for _ in 0..thread_count {
-        let the_world = Arc::clone(&the_world);
-        let camera = Arc::clone(&camera);
-        let rx = Arc::clone(&rx);
-        let row_tx = row_tx.clone();
-
-        threads.push(thread::spawn(move || {
-            loop {
-                let (enumerator, j) = match rx.lock().unwrap().recv() {
-                    Ok((enumerator, j)) => (enumerator, j),
-                    Err(_) => {
-                        // eprintln!("exiting thread: {}", e);
-                        return;
+        None => (pose.vfov, 0.1, 1.),
+    };
+    let frame = Frame::new(lookfrom, lookat, vup);
+    // the camera of the options, focused at `focus_distance`
+    let build = |aperture_shape: Aperture, aperture: f64, focus_distance: f64| {
+        let camera: Box<dyn Camera> = match args.projection {
+            Projection::Perspective => Box::new(
+                ThinLensCamera::new(
+                    lookfrom,
+                    lookat,
+                    vup,
+                    vfov,
+                    aspect_ratio,
+                    aperture,
+                    focus_distance,
+                )
+                .with_aperture(aperture_shape)
+                .with_anamorphic_squeeze(args.anamorphic_squeeze)
+                .with_optical_vignetting(args.optical_vignetting),
+            ),
+            Projection::Orthographic => {
+                let view_width = args.view_width.unwrap_or_else(|| {
+                    let half_height = (utils::degrees_to_radians(vfov) / 2.).tan();
+                    2. * half_height * focus_distance * aspect_ratio
+                });
+                Box::new(OrthographicCamera::new(
+                    lookfrom,
+                    lookat,
+                    vup,
+                    view_width,
+                    aspect_ratio,
+                ))
+            }
+            Projection::Equirectangular => {
+                Box::new(EquirectangularCamera::new(lookfrom, lookat, vup))
+            }
+            Projection::Fisheye => Box::new(FisheyeCamera::new(
+                lookfrom,
+                lookat,
+                vup,
+                args.fisheye_mapping,
+                args.fov,
+                aspect_ratio,
+            )),
+        };
+        let camera: Arc<dyn Camera> = match args.stereo {
+            Some(layout) => {
+                let frame = Frame::new(lookfrom, lookat, vup);
+                let convergence = args.convergence.unwrap_or(focus_distance);
+                Arc::new(match args.projection {
+                    Projection::Equirectangular => {
+                        StereoCamera::omnidirectional(camera, frame, layout, args.ipd, convergence)
                     }
-                };
-                let start = std::time::Instant::now();
-                let mut colors = Vec::with_capacity(IMAGE_WIDTH as usize);
-
-                for i in 0..IMAGE_WIDTH {
-                    let color = calc_color(&camera, &the_world, i, j);
-                    colors.push(color);
-                }
-                row_tx.send(Row { colors, enumerator }).unwrap();
-                eprintln!("{}", format_elapsed(start, j));
+                    _ => StereoCamera::new(camera, frame, layout, args.ipd, convergence),
+                })
             }
-        }));
-    }
+            None => Arc::from(camera),
+        };
+        camera
+    };
 
-    drop(row_tx);
+    let focus_target: Option<(&dyn Hittable, Ray)> = match (&args.focus_pixel, &args.focus_on) {
+        (&Some((x, y)), _) => {
+            let (width, height) = image_size(args);
+            if x >= width as u32 || y >= height as u32 {
+                return Err(Error::PixelOutsideImage {
+                    x,
+                    y,
+                    width,
+                    height,
+                });
+            }
+            // the ray through the center of the pixel as the image maps it, whatever the
+            // projection and stereo layout
+            let pinhole = build(Aperture::Circle, 0., pose.focus_distance);
+            let u = (x as f64 + 0.5) / (width - 1) as f64;
+            let v = (height as f64 - 0.5 - y as f64) / (height - 1) as f64;
+            let mut sampler = SamplerKind::Independent.create(1, seed);
+            pinhole.get_ray(u, v, sampler.as_mut()).map(|mut ray| {
+                ray.time = time;
+                (the_world as &dyn Hittable, ray)
+            })
+        }
+        (None, Some(name)) => {
+            let object = the_world
+                .named(name)
+                .ok_or_else(|| Error::UnknownObject(name.clone()))?;
+            object.center(time).map(|center| {
+                (
+                    object as &dyn Hittable,
+                    Ray::with_time(lookfrom, center - lookfrom, time),
+                )
+            })
+        }
+        (None, None) => None,
+    };
+    let dist_to_focus = match focus_target {
+        Some((target, ray)) => match camera::autofocus(target, &ray, &frame) {
+            Some(distance) => {
+                reporter.message(&format!("focus distance {:.3}", distance));
+                distance
+            }
+            None => {
+                reporter.message("nothing to focus on, keeping the focus distance");
+                pose.focus_distance
+            }
+        },
+        None => pose.focus_distance,
+    };
+    let aperture_shape = match (&args.aperture_mask, args.aperture_blades) {
+        (Some(path), _) => Aperture::Mask(ApertureMask::load(path)?),
+        (None, Some(blades)) => Aperture::Polygon {
+            blades,
+            rotation: args.aperture_rotation,
+        },
+        (None, None) => Aperture::Circle,
+    };
+    let camera = build(aperture_shape, aperture, dist_to_focus);
+    let (open, close) = args.shutter_interval;
+    let camera = Arc::new(ShutterCamera::new(camera, time + open, time + close));
+    Ok(FrameCamera { camera, exposure })
+}
 
-    let mut heap = BinaryHeap::new();
-    let mut heap_cursor = 0;
+/// Renders `camera` into `accumulation` in passes until the sample budget or the time
+/// limit is used up, writing the image to `output` or stdout.
+fn render_frame(
+    args: &cli::Args,
+    reporter: &ProgressReporter,
+    settings: RenderSettings,
+    the_world: &Arc<World>,
+    camera: &FrameCamera,
+    accumulation: &mut Accumulation,
+    output: Option<&str>,
+) -> Result<()> {
+    let area = render_area(args);
+    let scene = scene_fingerprint(args)?;
+    let budget = settings.budget;
+    let exposure = camera.exposure;
+    let camera = &camera.camera;
+    let save_checkpoint = |accumulation: &Accumulation| -> Result<()> {
+        if let Some(path) = &args.checkpoint {
+            checkpoint::save(path, &area, scene, &settings, accumulation)?;
+        }
+        Ok(())
+    };
+    let tiles = Arc::new(scheduler::tiles(area, args.tile_size, args.tile_order));
+    let save_image = |accumulation: &Accumulation, path: &str| {
+        output_film(&accumulation.film, &area, args.crop_output, exposure).save_ppm(path)
+    };
 
-    for row in row_rx {
-        heap.push(row);
+    // without progressive mode or time limit the whole budget is rendered in one pass
+    let pass_samples = match (args.progressive, args.time_limit) {
+        (Some(samples), _) => samples,
+        (None, Some(_)) => TIME_LIMIT_PASS_SAMPLES,
+        (None, None) => budget.max_samples,
+    }
+    .max(1);
+    let started = Instant::now();
+    let mut passes = 0;
+    // a resumed render starts with the passes its pixels have not finished
+    let mut sample_end = accumulation.min_samples(&area);
+
+    let total_tiles = tiles.len() as f64
+        * budget
+            .max_samples
+            .saturating_sub(sample_end)
+            .div_ceil(pass_samples) as f64;
+    let mut tiles_done = 0;
+    let checkpoint_interval = Duration::from_secs(args.checkpoint_interval);
+    let mut last_checkpoint = Instant::now();
+    let mut after_tile = |_: &Tile, accumulation: &Accumulation| {
+        if last_checkpoint.elapsed() >= checkpoint_interval {
+            save_checkpoint(accumulation)?;
+            last_checkpoint = Instant::now();
+        }
+        tiles_done += 1;
+        let mut fraction = tiles_done as f64 / total_tiles.max(1.);
+        if let Some(limit) = args.time_limit {
+            fraction = fraction.max(started.elapsed().as_secs_f64() / limit.as_secs_f64());
+        }
+        reporter.update(fraction.min(1.), &accumulation.counts);
+        match args.time_limit {
+            // stops the pass, the tiles rendered so far are kept
+            Some(limit) if started.elapsed() >= limit => Err(Error::Cancelled),
+            _ => Ok(()),
+        }
+    };
 
-        while let Some(row) = heap.peek() {
-            if row.enumerator != heap_cursor {
+    while sample_end < budget.max_samples {
+        sample_end = sample_end
+            .saturating_add(pass_samples)
+            .min(budget.max_samples);
+
+        let rendered = if args.single_thread {
+            render::single_thread(
+                camera.as_ref(),
+                the_world,
+                settings,
+                &tiles,
+                accumulation,
+                sample_end,
+                &mut after_tile,
+            )
+        } else {
+            render::multiple_threads(
+                camera,
+                the_world,
+                settings,
+                &tiles,
+                accumulation,
+                sample_end,
+                &mut after_tile,
+            )
+        };
+        passes += 1;
+        match rendered {
+            Ok(()) => save_checkpoint(accumulation)?,
+            Err(Error::Cancelled) => {
+                save_checkpoint(accumulation)?;
+                if let Some(limit) = args.time_limit {
+                    reporter.message(&format!("time limit of {:?} reached", limit));
+                }
                 break;
             }
-            if let Some(row) = heap.pop() {
-                for color in row.colors {
-                    Vec3::write_color(color, SAMPLES_PER_PIXEL);
-                }
+            Err(error) => return Err(error),
+        }
+
+        if args.progressive.is_some() {
+            if let Some(path) = output {
+                save_image(accumulation, path)?;
             }
-            heap_cursor += 1;
+            reporter.message(&format!(
+                "pass done: up to {} of {} samples per pixel",
+                sample_end, budget.max_samples
+            ));
         }
     }
 
-    assert_eq!(heap.len(), 0);
-
-    for handle in threads {
-        handle.join().unwrap();
+    match output {
+        Some(path) => save_image(accumulation, path)?,
+        None => {
+            let stdout = io::stdout();
+            let mut out = BufWriter::new(stdout.lock());
+            output_film(&accumulation.film, &area, args.crop_output, exposure)
+                .write_ppm(&mut out)?;
+            out.flush()?;
+        }
     }
-}
 
-fn single_thread(camera: &camera::Camera, the_world: &world::World) {
-    // rendering from left upper corner to right lower corner
-    for j in (0..IMAGE_HEIGHT).rev() {
-        eprintln!("Processing {} rows. Remains {}", IMAGE_HEIGHT, j + 1);
-        let start = std::time::Instant::now();
+    reporter.finish(&accumulation.counts);
+    reporter.message(&format!(
+        "{} passes, average samples per pixel {:.2}",
+        passes,
+        accumulation.samples_per_pixel(&area)
+    ));
+    Ok(())
+}
 
-        for i in 0..IMAGE_WIDTH {
-            let color = calc_color(camera, the_world, i, j);
-            Vec3::write_color(color, SAMPLES_PER_PIXEL);
+/// Width to height of the whole image. Panoramas are as wide as their projection needs,
+/// with stereo both views together.
+fn image_aspect_ratio(args: &cli::Args) -> f64 {
+    match (args.projection, args.stereo) {
+        (Projection::Equirectangular, Some(layout)) => {
+            layout.image_aspect_ratio(PANORAMA_ASPECT_RATIO)
         }
-        eprintln!("{}", format_elapsed(start, j));
-        println!();
+        (Projection::Equirectangular, None) => PANORAMA_ASPECT_RATIO,
+        _ => ASPECT_RATIO,
     }
 }
 
-fn calc_color(camera: &camera::Camera, the_world: &world::World, i: i32, j: i32) -> Color {
-    let mut color = Color::new(0., 0., 0.);
-
-    for _ in 0..SAMPLES_PER_PIXEL {
-        let u = (i as f64 + thread_rng().gen::<f64>()) / (IMAGE_WIDTH - 1) as f64;
-        let v = (j as f64 + thread_rng().gen::<f64>()) / (IMAGE_HEIGHT - 1) as f64;
-
-        color += camera.get_ray(u, v).ray_color(&the_world, MAX_DEPTH);
-    }
-
-    color
+fn image_size(args: &cli::Args) -> (i32, i32) {
+    let height = (IMAGE_WIDTH as f64 / image_aspect_ratio(args)) as i32;
+    (IMAGE_WIDTH, height)
 }
 
-struct Row {
-    colors: Vec<Color>,
-    enumerator: usize,
+fn render_area(args: &cli::Args) -> Tile {
+    let (width, height) = image_size(args);
+    match args.crop {
+        Some(crop) => crop.resolve(width, height),
+        None => Tile::image(width, height),
+    }
 }
 
-impl Ord for Row {
-    fn cmp(&self, other: &Self) -> Ordering {
-        // reverse order: from smallest to biggest
-        other.enumerator.cmp(&self.enumerator)
+/// Hash of the options a checkpoint's samples depend on besides its render settings,
+/// including the files they name. The exposure is applied on output and left out.
+fn scene_fingerprint(args: &cli::Args) -> Result<u64> {
+    let read = |path: &Option<String>| -> Result<Option<String>> {
+        path.as_ref()
+            .map(|path| Ok(fs::read_to_string(path)?))
+            .transpose()
+    };
+    let mut animations = Vec::new();
+    for (name, path) in &args.animate {
+        animations.push((name, fs::read_to_string(path)?));
     }
+    let camera = format!(
+        "{:?}",
+        (
+            args.projection,
+            args.view_width,
+            args.focus_pixel,
+            &args.focus_on,
+            (args.focal_length, args.sensor_width, args.sensor_height),
+            (args.f_number, args.shutter),
+            (args.aperture_blades, args.aperture_rotation),
+            read(&args.aperture_mask)?,
+            (args.anamorphic_squeeze, args.optical_vignetting),
+            (args.fisheye_mapping, args.fov),
+            (args.stereo, args.ipd, args.convergence),
+            read(&args.camera_path)?,
+        )
+    );
+    let scene = format!(
+        "{:?}",
+        (
+            (args.conductor, args.roughness, args.anisotropy),
+            (args.glass_ior, args.glass_roughness, args.glass_color),
+            args.glass_absorption_distance,
+            (args.glass_dispersion, args.glass_abbe),
+            animations,
+            args.interpolation,
+            args.shutter_interval,
+        )
+    );
+    Ok(checkpoint::fingerprint(&(camera + &scene)))
 }
 
-impl PartialOrd for Row {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+/// materials of the large spheres picked by the options
+fn cover_materials(args: &cli::Args) -> CoverMaterials {
+    let absorption = match args.glass_color {
+        // clap makes sure the distance is above zero
+        Some(color) => {
+            Absorption::new(color, args.glass_absorption_distance).unwrap_or_else(Absorption::none)
+        }
+        None => Absorption::none(),
+    };
+    let glass: Box<dyn Material + Send + Sync> = match args.glass_roughness {
+        Some(roughness) => Box::new(
+            RoughDielectric::new(args.glass_ior, Ggx::from_roughness(roughness, 0.))
+                .with_absorption(absorption),
+        ),
+        None => {
+            let glass = Dielectric::new(args.glass_ior).with_absorption(absorption);
+            let dispersion = match (args.glass_dispersion, args.glass_abbe) {
+                (Some(preset), _) => Some(preset.dispersion()),
+                (None, Some(abbe)) => Some(Dispersion::from_abbe(args.glass_ior, abbe)),
+                (None, None) => None,
+            };
+            match dispersion {
+                Some(dispersion) => Box::new(glass.with_dispersion(dispersion)),
+                None => Box::new(glass),
+            }
+        }
+    };
+    let mut cover = CoverMaterials {
+        glass,
+        ..CoverMaterials::default()
+    };
+    if let Some(preset) = args.conductor {
+        let distribution = Ggx::from_roughness(args.roughness, args.anisotropy);
+        cover.metal = Box::new(GgxConductor::preset(preset, distribution));
     }
+    cover
 }
 
-impl PartialEq for Row {
-    fn eq(&self, other: &Self) -> bool {
-        self.enumerator.eq(&other.enumerator)
+/// the rendered pixels as they are written out, see `CropOutput`
+fn output_film(film: &Film, area: &Tile, crop_output: CropOutput, exposure: f64) -> Film {
+    let mut cropped = film.cropped(area);
+    cropped.scale(exposure);
+    match crop_output {
+        CropOutput::Cropped => cropped,
+        CropOutput::Full => {
+            let mut full = Film::new(film.width(), film.height());
+            full.merge(&cropped);
+            full
+        }
     }
 }
 
-impl Eq for Row {}
-
-fn format_elapsed(start: Instant, j: i32) -> String {
-    let elapsed = start.elapsed();
-    format!(
-        "time elapsed on {} {:?} {:?}",
-        j,
-        elapsed,
-        elapsed.as_nanos(),
-    )
+fn settings_from_args(args: &cli::Args) -> RenderSettings {
+    let max_samples = match (args.max_samples, args.time_limit) {
+        (Some(samples), _) => samples,
+        // time limited renders keep going until the clock stops them
+        (None, Some(_)) => adaptive::UNLIMITED_SAMPLES,
+        (None, None) => SAMPLES_PER_PIXEL,
+    };
+    let budget = match args.adaptive_threshold {
+        Some(threshold) => SampleBudget::adaptive(args.min_samples, max_samples, threshold),
+        None => SampleBudget::fixed(max_samples),
+    };
+    RenderSettings {
+        sampler: args.sampler,
+        seed: args.seed.unwrap_or_else(|| thread_rng().gen::<u64>()),
+        budget,
+        filter: args.filter,
+        filter_radius: args
+            .filter_radius
+            .unwrap_or_else(|| args.filter.default_radius()),
+        // dispersion follows single wavelengths
+        spectral: args.spectral || args.glass_dispersion.is_some() || args.glass_abbe.is_some(),
+        illuminant: args.illuminant,
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::utils::compare_floats;
-    use crate::vec3::Vec3;
+    use ray_tracing_one_weekend::utils::compare_floats;
+    use ray_tracing_one_weekend::vec3::Vec3;
 
     #[test]
     fn test_sum_origins() {
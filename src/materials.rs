@@ -1,68 +1,112 @@
+use std::sync::OnceLock;
+
 use crate::hittable::HitRecord;
+use crate::microfacet::{Ggx, ShadingFrame};
 use crate::ray::Ray;
-use crate::utils;
+use crate::sampler::{self, Sampler};
+use crate::spectrum::{self, Dispersion, RgbSpectrum, SampledSpectrum};
 use crate::vec3::{Color, Vec3};
 
 pub trait Material {
-    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Option<ScatterData>;
+    fn scatter(
+        &self,
+        ray: &Ray,
+        record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterData>;
 }
 
 pub struct ScatterData {
     pub attenuation: Color,
+    /// The attenuation at the wavelengths of the ray, when it carries some. Without it
+    /// `attenuation` is interpolated, see `spectrum::interpolate_rgb`.
+    pub spectrum: Option<SampledSpectrum>,
     pub scattered: Ray,
 }
 
+/// A color upsampled to a spectrum the first time a path with wavelengths needs it.
+struct Albedo {
+    color: Color,
+    spectrum: OnceLock<RgbSpectrum>,
+}
+
+impl Albedo {
+    fn new(color: Color) -> Self {
+        Self {
+            color,
+            spectrum: OnceLock::new(),
+        }
+    }
+
+    fn sample(&self, ray: &Ray) -> Option<SampledSpectrum> {
+        let wavelengths = ray.wavelengths?;
+        let spectrum = self.spectrum.get_or_init(|| RgbSpectrum::fit(self.color));
+        Some(spectrum.sample(&wavelengths))
+    }
+}
+
 pub struct Lambertian {
-    albedo: Color,
+    albedo: Albedo,
 }
 
 impl Lambertian {
     pub fn new(color: Color) -> Self {
-        Self { albedo: color }
+        Self {
+            albedo: Albedo::new(color),
+        }
     }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _ray: &Ray, record: &HitRecord) -> Option<ScatterData> {
-        let scatter_direction = record.normal + Vec3::random_unit_vector();
+    fn scatter(
+        &self,
+        ray: &Ray,
+        record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterData> {
+        let scatter_direction = record.normal + sampler::sample_unit_sphere(sampler.get_2d());
         let scatter_direction = if scatter_direction.near_zero() {
             record.normal
         } else {
             scatter_direction
         };
-        let scattered = Ray::new(record.point, scatter_direction);
+        let scattered = ray.scattered(record.point, scatter_direction);
         Some(ScatterData {
-            attenuation: self.albedo,
+            attenuation: self.albedo.color,
+            spectrum: self.albedo.sample(ray),
             scattered,
         })
     }
 }
 
 pub struct Metal {
-    albedo: Color,
+    albedo: Albedo,
     fuzz: f64,
 }
 
 impl Metal {
     pub fn new(color: Color, fuzz: f64) -> Self {
         Self {
-            albedo: color,
+            albedo: Albedo::new(color),
             fuzz: fuzz.min(1.0),
         }
     }
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Option<ScatterData> {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterData> {
         let reflected = reflect(ray.direction.unit_vector(), record.normal);
-        let attenuation = self.albedo;
-        let scattered = Ray::new(
-            record.point,
-            reflected + self.fuzz * Vec3::random_in_unit_sphere(),
-        );
+        let fuzz_direction = sampler::sample_unit_ball(sampler.get_2d(), sampler.get_1d());
+        let scattered = ray.scattered(record.point, reflected + self.fuzz * fuzz_direction);
         if scattered.direction.dot(&record.normal) > 0. {
             Some(ScatterData {
-                attenuation,
+                attenuation: self.albedo.color,
+                spectrum: self.albedo.sample(ray),
                 scattered,
             })
         } else {
@@ -71,14 +115,191 @@ impl Material for Metal {
     }
 }
 
+/// Complex index of refraction of metals at the red, green and blue wavelengths.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConductorPreset {
+    Gold,
+    Silver,
+    Copper,
+    Aluminium,
+    Iron,
+    Chromium,
+}
+
+impl ConductorPreset {
+    /// the real part n and the extinction coefficient k
+    pub fn ior(self) -> (Color, Color) {
+        let (n, k) = match self {
+            ConductorPreset::Gold => ([0.143, 0.374, 1.442], [3.983, 2.385, 1.603]),
+            ConductorPreset::Silver => ([0.155, 0.117, 0.138], [4.828, 3.122, 2.147]),
+            ConductorPreset::Copper => ([0.200, 0.924, 1.102], [3.912, 2.452, 2.142]),
+            ConductorPreset::Aluminium => ([1.657, 0.880, 0.521], [9.224, 6.270, 4.837]),
+            ConductorPreset::Iron => ([2.911, 2.950, 2.585], [3.089, 2.932, 2.767]),
+            ConductorPreset::Chromium => ([3.107, 3.181, 2.323], [3.331, 3.329, 3.135]),
+        };
+        (Color::new(n[0], n[1], n[2]), Color::new(k[0], k[1], k[2]))
+    }
+}
+
+/// Rough metal made of GGX microfacets that reflect like a perfect mirror, with the
+/// Fresnel reflectance of a complex index of refraction.
+pub struct GgxConductor {
+    eta: Color,
+    k: Color,
+    distribution: Ggx,
+}
+
+impl GgxConductor {
+    pub fn new(eta: Color, k: Color, distribution: Ggx) -> Self {
+        Self {
+            eta,
+            k,
+            distribution,
+        }
+    }
+
+    pub fn preset(preset: ConductorPreset, distribution: Ggx) -> Self {
+        let (eta, k) = preset.ior();
+        Self::new(eta, k, distribution)
+    }
+}
+
+impl Material for GgxConductor {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterData> {
+        let frame = ShadingFrame::new(record.normal);
+        let wo = frame.to_local(-ray.direction.unit_vector());
+        if wo.z <= 0. {
+            return None;
+        }
+        let m = self
+            .distribution
+            .sample_visible_normal(wo, sampler.get_2d());
+        let wi = reflect(-wo, m);
+        if wi.z <= 0. {
+            // reflected into the surface, a single bounce cannot leave
+            return None;
+        }
+
+        // with visible normals sampled, the weight is what is left of f * cos / pdf
+        let cosine = wo.dot(&m);
+        let fresnel = Color::new(
+            fresnel_conductor(cosine, self.eta.x, self.k.x),
+            fresnel_conductor(cosine, self.eta.y, self.k.y),
+            fresnel_conductor(cosine, self.eta.z, self.k.z),
+        );
+        let shadowing = self.distribution.g2(wo, wi) / self.distribution.g1(wo);
+        let spectrum = ray.wavelengths.map(|wavelengths| {
+            SampledSpectrum::from_fn(&wavelengths, |lambda| {
+                let eta = spectrum::interpolate_rgb(self.eta, lambda);
+                let k = spectrum::interpolate_rgb(self.k, lambda);
+                fresnel_conductor(cosine, eta, k) * shadowing
+            })
+        });
+        Some(ScatterData {
+            attenuation: fresnel * shadowing,
+            spectrum,
+            scattered: ray.scattered(record.point, frame.to_world(wi)),
+        })
+    }
+}
+
+/// Light absorbed inside a medium following the Beer-Lambert law, the longer the way
+/// through it the darker and more saturated the color.
+#[derive(Clone, Copy, Debug)]
+pub struct Absorption {
+    /// per unit of distance, for red, green and blue
+    coefficient: Color,
+}
+
+impl Absorption {
+    /// clear, nothing is absorbed
+    pub fn none() -> Self {
+        Self {
+            coefficient: Color::origin(),
+        }
+    }
+
+    /// The medium lets through `transmittance` of the light after `distance`. A
+    /// transmittance of 0 is clamped to a tiny fraction. None unless the distance is
+    /// finite and above zero.
+    pub fn new(transmittance: Color, distance: f64) -> Option<Self> {
+        if !(distance.is_finite() && distance > 0.) {
+            return None;
+        }
+        let coefficient = |channel: f64| -channel.clamp(1e-9, 1.).ln() / distance;
+        Some(Self {
+            coefficient: Color::new(
+                coefficient(transmittance.x),
+                coefficient(transmittance.y),
+                coefficient(transmittance.z),
+            ),
+        })
+    }
+
+    /// What is left of the light along `ray`, which crossed the medium if it hit the
+    /// surface from inside.
+    fn transmittance(&self, ray: &Ray, record: &HitRecord) -> Color {
+        if record.front_face {
+            return Color::new(1., 1., 1.);
+        }
+        let distance = record.t * ray.direction.length();
+        Color::new(
+            (-self.coefficient.x * distance).exp(),
+            (-self.coefficient.y * distance).exp(),
+            (-self.coefficient.z * distance).exp(),
+        )
+    }
+
+    /// `transmittance` at the wavelengths of the ray, if it carries some
+    fn sample(&self, ray: &Ray, record: &HitRecord) -> Option<SampledSpectrum> {
+        let wavelengths = ray.wavelengths?;
+        let distance = if record.front_face {
+            0.
+        } else {
+            record.t * ray.direction.length()
+        };
+        Some(SampledSpectrum::from_fn(&wavelengths, |lambda| {
+            (-spectrum::interpolate_rgb(self.coefficient, lambda) * distance).exp()
+        }))
+    }
+}
+
 #[derive(Clone)]
 pub struct Dielectric {
     refraction_index: f64,
+    absorption: Absorption,
+    /// replaces `refraction_index` when set
+    dispersion: Option<Dispersion>,
 }
 
 impl Dielectric {
+    /// `refraction_index` has to be above zero
     pub fn new(refraction_index: f64) -> Self {
-        Self { refraction_index }
+        Self {
+            refraction_index,
+            absorption: Absorption::none(),
+            dispersion: None,
+        }
+    }
+
+    /// Glass splitting light into its colors in spectral renders, paths reaching it
+    /// follow a single wavelength from then on, see `Wavelengths`. RGB paths see the
+    /// index at the yellow helium line.
+    pub fn with_dispersion(self, dispersion: Dispersion) -> Self {
+        Self {
+            dispersion: Some(dispersion),
+            ..self
+        }
+    }
+
+    /// Tinted glass, see `Absorption::new`.
+    pub fn with_absorption(self, absorption: Absorption) -> Self {
+        Self { absorption, ..self }
     }
 
     fn refract(unit_direction: Vec3, normal: Vec3, etai_over_etat: f64) -> Vec3 {
@@ -91,12 +312,32 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray: &Ray, record: &HitRecord) -> Option<ScatterData> {
-        let attenuation = Color::new(1., 1., 1.);
+    fn scatter(
+        &self,
+        ray: &Ray,
+        record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterData> {
+        let attenuation = self.absorption.transmittance(ray, record);
+        let mut spectrum = self.absorption.sample(ray, record);
+        let mut wavelengths = ray.wavelengths;
+        let refraction_index = match &self.dispersion {
+            Some(dispersion) => match &mut wavelengths {
+                Some(wavelengths) => {
+                    if !wavelengths.is_secondary_terminated() {
+                        spectrum = spectrum.map(SampledSpectrum::terminate_secondary);
+                    }
+                    wavelengths.terminate_secondary();
+                    dispersion.ior(wavelengths.hero())
+                }
+                None => dispersion.n_d(),
+            },
+            None => self.refraction_index,
+        };
         let refraction_ratio = if record.front_face {
-            1.0 / self.refraction_index
+            1.0 / refraction_index
         } else {
-            self.refraction_index
+            refraction_index
         };
 
         let unit_direction = ray.direction.unit_vector();
@@ -104,28 +345,265 @@ impl Material for Dielectric {
         let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
         let reflect_value = reflectance(cos_theta, refraction_ratio);
-        let has_reflectance = reflect_value > utils::random_double();
+        let has_reflectance = reflect_value > sampler.get_1d();
         let direction = if cannot_refract || has_reflectance {
             reflect(unit_direction, record.normal)
         } else {
             Dielectric::refract(unit_direction, record.normal, refraction_ratio)
         };
-        let scattered = Ray::new(record.point, direction);
+        let mut scattered = ray.scattered(record.point, direction);
+        scattered.wavelengths = wavelengths;
 
         Some(ScatterData {
             attenuation,
+            spectrum,
             scattered,
         })
     }
 }
 
+/// Frosted glass: GGX microfacets that each reflect or refract like a smooth interface
+/// (Walter et al., "Microfacet Models for Refraction through Rough Surfaces", 2007).
+pub struct RoughDielectric {
+    refraction_index: f64,
+    distribution: Ggx,
+    absorption: Absorption,
+}
+
+impl RoughDielectric {
+    /// `refraction_index` has to be above zero
+    pub fn new(refraction_index: f64, distribution: Ggx) -> Self {
+        Self {
+            refraction_index,
+            distribution,
+            absorption: Absorption::none(),
+        }
+    }
+
+    /// Tinted frosted glass, see `Absorption::new`.
+    pub fn with_absorption(self, absorption: Absorption) -> Self {
+        Self { absorption, ..self }
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        record: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterData> {
+        // ratio of the index on the other side to the one the ray comes from
+        let eta = if record.front_face {
+            self.refraction_index
+        } else {
+            1. / self.refraction_index
+        };
+        let frame = ShadingFrame::new(record.normal);
+        let wo = frame.to_local(-ray.direction.unit_vector());
+        if wo.z <= 0. {
+            return None;
+        }
+        let m = self
+            .distribution
+            .sample_visible_normal(wo, sampler.get_2d());
+        let cos_i = wo.dot(&m);
+        let fresnel = fresnel_dielectric(cos_i, eta);
+
+        // reflection is picked with the probability of the Fresnel term, it cancels out
+        // of the weight like the distribution does with visible normals
+        let wi = if sampler.get_1d() < fresnel {
+            let wi = reflect(-wo, m);
+            if wi.z <= 0. {
+                return None;
+            }
+            wi
+        } else {
+            let cos_t = (1. - (1. - cos_i * cos_i) / (eta * eta)).max(0.).sqrt();
+            let wi = -wo / eta + (cos_i / eta - cos_t) * m;
+            if wi.z >= 0. {
+                return None;
+            }
+            wi
+        };
+        let shadowing = self.distribution.g2(wo, wi) / self.distribution.g1(wo);
+        Some(ScatterData {
+            attenuation: self.absorption.transmittance(ray, record) * shadowing,
+            spectrum: self
+                .absorption
+                .sample(ray, record)
+                .map(|spectrum| spectrum * shadowing),
+            scattered: ray.scattered(record.point, frame.to_world(wi)),
+        })
+    }
+}
+
 fn reflect(v: Vec3, n: Vec3) -> Vec3 {
     v - 2.0 * v.dot(&n) * n
 }
 
+/// Fraction of unpolarized light reflected by a conductor of the complex index of
+/// refraction `eta` + i `k` at the angle with the cosine `cos_theta`.
+fn fresnel_conductor(cos_theta: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_theta.clamp(0., 1.).powi(2);
+    let sin2 = 1. - cos2;
+    let t0 = eta * eta - k * k - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4. * eta * eta * k * k).sqrt();
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.).sqrt();
+
+    let t1 = a2_plus_b2 + cos2;
+    let t2 = 2. * cos_theta * a;
+    let perpendicular = (t1 - t2) / (t1 + t2);
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let parallel = perpendicular * (t3 - t4) / (t3 + t4);
+    (perpendicular + parallel) / 2.
+}
+
+/// Fraction of unpolarized light reflected at a smooth interface, `eta` is the index of
+/// the other side over the one of the side of the incoming light. Total internal
+/// reflection gives 1.
+fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let cos_i = cos_i.clamp(0., 1.);
+    let sin2_t = (1. - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1. {
+        return 1.;
+    }
+    let cos_t = (1. - sin2_t).sqrt();
+    let perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    (perpendicular * perpendicular + parallel * parallel) / 2.
+}
+
 fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
     // use Schlick's approximation for reflectance
     let r0 = (1. - ref_idx) / (1. + ref_idx);
     let r0 = r0.powi(2);
     r0 + (1. - r0) * (1. - cosine).powi(5)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::SamplerKind;
+
+    #[test]
+    fn test_fresnel_conductor() {
+        let (eta, k) = (0.2, 3.9);
+        // at normal incidence it is ((n - 1)^2 + k^2) / ((n + 1)^2 + k^2)
+        let expected = ((eta - 1.) * (eta - 1.) + k * k) / ((eta + 1.) * (eta + 1.) + k * k);
+        assert!((fresnel_conductor(1., eta, k) - expected).abs() < 1e-12);
+        // every metal turns into a mirror at grazing angles
+        assert!((fresnel_conductor(0., eta, k) - 1.).abs() < 1e-12);
+        assert!(fresnel_conductor(0.5, eta, k) < 1.);
+    }
+
+    #[test]
+    fn test_conductor_reflects_above_surface() {
+        let conductor = GgxConductor::preset(ConductorPreset::Gold, Ggx::from_roughness(0.5, 0.3));
+        let normal = Vec3::new(0., 0., 1.);
+        let record = HitRecord {
+            point: Vec3::origin(),
+            normal,
+            t: 1.,
+            front_face: true,
+            material: &conductor,
+        };
+        let ray = Ray::new(Vec3::new(-1., 0., 1.), Vec3::new(1., 0.2, -1.));
+        let mut sampler = SamplerKind::Independent.create(1, 3);
+        for index in 0..256 {
+            sampler.start_pixel_sample(0, 0, index);
+            let Some(data) = conductor.scatter(&ray, &record, sampler.as_mut()) else {
+                continue;
+            };
+            assert!(data.scattered.direction.dot(&normal) > 0.);
+            // a passive surface reflects at most what comes in
+            let weight = data.attenuation;
+            assert!(
+                weight.x <= 1. && weight.y <= 1. && weight.z <= 1.,
+                "{}",
+                weight
+            );
+            assert!(weight.x > weight.z, "gold reflects more red than blue");
+        }
+    }
+
+    #[test]
+    fn test_rough_glass_at_normal_incidence() {
+        let glass = RoughDielectric::new(1.5, Ggx::from_roughness(0.4, 0.));
+        let normal = Vec3::new(0., 0., 1.);
+        let record = HitRecord {
+            point: Vec3::origin(),
+            normal,
+            t: 1.,
+            front_face: true,
+            material: &glass,
+        };
+        let ray = Ray::new(Vec3::new(0., 0., 1.), -normal);
+        let mut sampler = SamplerKind::Independent.create(1, 5);
+        let (mut reflected, mut transmitted) = (0, 0);
+        for index in 0..512 {
+            sampler.start_pixel_sample(0, 0, index);
+            let Some(data) = glass.scatter(&ray, &record, sampler.as_mut()) else {
+                continue;
+            };
+            let direction = data.scattered.direction.unit_vector();
+            if direction.dot(&normal) < 0. {
+                transmitted += 1;
+                // refracted towards -n, mostly straight through
+                assert!(direction.dot(&-normal) > 0.5, "{}", direction);
+            } else {
+                reflected += 1;
+            }
+            let weight = data.attenuation;
+            assert!(weight.x <= 1. && weight.x > 0., "{}", weight);
+        }
+        // about 4% of the light is reflected head on
+        assert!(
+            transmitted > 10 * reflected,
+            "{} {}",
+            transmitted,
+            reflected
+        );
+        assert!(reflected > 0);
+    }
+
+    #[test]
+    fn test_absorption_over_distance() {
+        let absorption = Absorption::new(Color::new(0.5, 1., 0.25), 2.).unwrap();
+        let material = Lambertian::new(Color::origin());
+        let record = |t: f64, front_face: bool| HitRecord {
+            point: Vec3::origin(),
+            normal: Vec3::new(0., 0., 1.),
+            t,
+            front_face,
+            material: &material,
+        };
+        let ray = Ray::new(Vec3::origin(), Vec3::new(0., 0., 2.));
+        // 4 units inside the medium are twice the given distance
+        let leaving = absorption.transmittance(&ray, &record(2., false));
+        assert!((leaving - Color::new(0.25, 1., 0.0625)).length() < 1e-12);
+        let entering = absorption.transmittance(&ray, &record(2., true));
+        assert_eq!(entering, Color::new(1., 1., 1.));
+
+        // no distance would give 0 / 0 for the clear channel
+        let clear = Color::new(1., 1., 1.);
+        assert!(Absorption::new(clear, 0.).is_none());
+        assert!(Absorption::new(clear, -1.).is_none());
+        assert!(Absorption::new(clear, f64::NAN).is_none());
+    }
+
+    #[test]
+    fn test_fresnel_dielectric() {
+        assert!((fresnel_dielectric(1., 1.5) - 0.04).abs() < 1e-12);
+        // leaving glass beyond the critical angle of about 41.8 degrees
+        assert_eq!(fresnel_dielectric(45f64.to_radians().cos(), 1. / 1.5), 1.);
+        assert!(fresnel_dielectric(40f64.to_radians().cos(), 1. / 1.5) < 1.);
+        // Brewster's angle: the parallel part vanishes
+        let brewster = 1.5f64.atan().cos();
+        let perpendicular = fresnel_dielectric(brewster, 1.5) * 2.;
+        let cos_t = (1. - (1. - brewster * brewster) / 2.25).sqrt();
+        let expected = ((brewster - 1.5 * cos_t) / (brewster + 1.5 * cos_t)).powi(2);
+        assert!((perpendicular - expected).abs() < 1e-12);
+    }
+}
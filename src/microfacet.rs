@@ -0,0 +1,170 @@
+use std::f64::consts::PI;
+
+use crate::vec3::Vec3;
+
+/// Orthonormal basis around a surface normal, the normal is local z. The tangent runs
+/// around the y axis, like the lines of latitude of a globe.
+pub struct ShadingFrame {
+    tangent: Vec3,
+    bitangent: Vec3,
+    normal: Vec3,
+}
+
+impl ShadingFrame {
+    pub fn new(normal: Vec3) -> Self {
+        let axis = if normal.y.abs() < 0.999 {
+            Vec3::new(0., 1., 0.)
+        } else {
+            Vec3::new(1., 0., 0.)
+        };
+        let tangent = axis.cross(&normal).unit_vector();
+        let bitangent = normal.cross(&tangent);
+        Self {
+            tangent,
+            bitangent,
+            normal,
+        }
+    }
+
+    pub fn to_local(&self, vector: Vec3) -> Vec3 {
+        Vec3::new(
+            vector.dot(&self.tangent),
+            vector.dot(&self.bitangent),
+            vector.dot(&self.normal),
+        )
+    }
+
+    pub fn to_world(&self, vector: Vec3) -> Vec3 {
+        vector.x * self.tangent + vector.y * self.bitangent + vector.z * self.normal
+    }
+}
+
+/// GGX (Trowbridge-Reitz) distribution of microfacet normals in a `ShadingFrame`,
+/// `alpha_x` is the roughness along the tangent and `alpha_y` across it.
+#[derive(Clone, Copy, Debug)]
+pub struct Ggx {
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+impl Ggx {
+    /// From a perceptual `roughness` in [0, 1] and an `anisotropy` in [-1, 1] that
+    /// stretches the highlight along the tangent when positive and across it when
+    /// negative.
+    pub fn from_roughness(roughness: f64, anisotropy: f64) -> Self {
+        // very small alphas break down numerically, a mirror is close enough
+        let alpha = roughness.clamp(0., 1.).powi(2).max(1e-4);
+        let aspect = (1. - 0.9 * anisotropy.abs().min(1.)).sqrt();
+        let (along, across) = (alpha / aspect, alpha * aspect);
+        if anisotropy >= 0. {
+            Self {
+                alpha_x: along,
+                alpha_y: across,
+            }
+        } else {
+            Self {
+                alpha_x: across,
+                alpha_y: along,
+            }
+        }
+    }
+
+    /// density of microfacets with the normal `m`
+    pub fn d(&self, m: Vec3) -> f64 {
+        if m.z <= 0. {
+            return 0.;
+        }
+        let x = m.x / self.alpha_x;
+        let y = m.y / self.alpha_y;
+        let stretched = x * x + y * y + m.z * m.z;
+        1. / (PI * self.alpha_x * self.alpha_y * stretched * stretched)
+    }
+
+    /// Smith's auxiliary function, how much of the surface seen from `w` is hidden
+    pub fn lambda(&self, w: Vec3) -> f64 {
+        let cos2 = w.z * w.z;
+        if cos2 == 0. {
+            return f64::INFINITY;
+        }
+        let tan2 = ((self.alpha_x * w.x).powi(2) + (self.alpha_y * w.y).powi(2)) / cos2;
+        (-1. + (1. + tan2).sqrt()) / 2.
+    }
+
+    /// fraction of the microfacets seen from `w` that are not masked
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1. / (1. + self.lambda(w))
+    }
+
+    /// height-correlated masking and shadowing between the directions `wo` and `wi`
+    pub fn g2(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1. / (1. + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Samples a microfacet normal in proportion to how much of it is seen from `wo`
+    /// (Heitz, "Sampling the GGX Distribution of Visible Normals", 2018).
+    pub fn sample_visible_normal(&self, wo: Vec3, (u, v): (f64, f64)) -> Vec3 {
+        // stretch the view into the configuration of a hemisphere
+        let view = Vec3::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z).unit_vector();
+        let length2 = view.x * view.x + view.y * view.y;
+        let t1 = if length2 > 0. {
+            Vec3::new(-view.y, view.x, 0.) / length2.sqrt()
+        } else {
+            Vec3::new(1., 0., 0.)
+        };
+        let t2 = view.cross(&t1);
+
+        // a point on the disk, the half of it behind the view is squeezed away
+        let radius = u.sqrt();
+        let phi = 2. * PI * v;
+        let p1 = radius * phi.cos();
+        let p2 = radius * phi.sin();
+        let s = 0.5 * (1. + view.z);
+        let p2 = (1. - s) * (1. - p1 * p1).max(0.).sqrt() + s * p2;
+        let normal = p1 * t1 + p2 * t2 + (1. - p1 * p1 - p2 * p2).max(0.).sqrt() * view;
+
+        Vec3::new(
+            self.alpha_x * normal.x,
+            self.alpha_y * normal.y,
+            normal.z.max(0.),
+        )
+        .unit_vector()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_visible_normals_face_the_view() {
+        let ggx = Ggx::from_roughness(0.7, 0.5);
+        let wo = Vec3::new(0.8, -0.3, 0.2).unit_vector();
+        for i in 0..16 {
+            for j in 0..16 {
+                let sample = ((i as f64 + 0.5) / 16., (j as f64 + 0.5) / 16.);
+                let m = ggx.sample_visible_normal(wo, sample);
+                assert!((m.length() - 1.).abs() < 1e-9);
+                assert!(m.z >= 0. && m.dot(&wo) >= -1e-9, "{}", m);
+            }
+        }
+    }
+
+    #[test]
+    fn test_distribution_projects_to_one() {
+        // the projected area of all microfacets is the area of the surface
+        let ggx = Ggx::from_roughness(0.5, -0.3);
+        let steps = 400;
+        let mut sum = 0.;
+        for i in 0..steps {
+            let cos_theta = (i as f64 + 0.5) / steps as f64;
+            let sin_theta = (1. - cos_theta * cos_theta).sqrt();
+            for j in 0..steps {
+                let phi = 2. * PI * (j as f64 + 0.5) / steps as f64;
+                let m = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+                sum += ggx.d(m) * cos_theta;
+            }
+        }
+        let integral = sum * 2. * PI / (steps * steps) as f64;
+        assert!((integral - 1.).abs() < 1e-2, "{}", integral);
+    }
+}
@@ -0,0 +1,113 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use ray_tracing_one_weekend::counters::RayCounts;
+
+// the progress line is redrawn at most this often
+const REPORT_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Redraws one line on stderr with the progress, elapsed time, ETA and throughput.
+pub struct ProgressReporter {
+    started: Instant,
+    last_report: Cell<Option<Instant>>,
+    quiet: bool,
+}
+
+impl ProgressReporter {
+    pub fn new(quiet: bool) -> Self {
+        Self {
+            started: Instant::now(),
+            last_report: Cell::new(None),
+            quiet,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// `fraction` of the whole render is done, in [0,1]
+    pub fn update(&self, fraction: f64, counts: &RayCounts) {
+        if self.quiet {
+            return;
+        }
+        if let Some(last_report) = self.last_report.get() {
+            if last_report.elapsed() < REPORT_INTERVAL && fraction < 1. {
+                return;
+            }
+        }
+        self.last_report.set(Some(Instant::now()));
+
+        let elapsed = self.elapsed();
+        let eta = if fraction > 0. {
+            format_duration(elapsed.mul_f64((1. - fraction).max(0.) / fraction))
+        } else {
+            "?".to_string()
+        };
+        eprint!(
+            "\r{:5.1}% | elapsed {} | ETA {} | {:.2} Mrays/s   ",
+            100. * fraction.min(1.),
+            format_duration(elapsed),
+            eta,
+            counts.rays as f64 / elapsed.as_secs_f64().max(1e-9) / 1e6,
+        );
+    }
+
+    /// prints a line of its own over the progress line, which is redrawn on the next update
+    pub fn message(&self, text: &str) {
+        if !self.quiet {
+            eprintln!("\r{:<72}", text);
+        }
+    }
+
+    /// ends the progress line and prints totals of the render
+    pub fn finish(&self, counts: &RayCounts) {
+        if self.quiet {
+            return;
+        }
+        let elapsed = self.elapsed();
+        eprintln!();
+        eprintln!("rendered in {}", format_duration(elapsed));
+        eprintln!(
+            "rays: {} total, {} primary, {} secondary, {:.2} Mrays/s",
+            counts.rays,
+            counts.primary_rays,
+            counts.secondary_rays(),
+            counts.rays as f64 / elapsed.as_secs_f64().max(1e-9) / 1e6,
+        );
+        eprintln!("intersection tests: {}", counts.intersection_tests);
+        eprintln!(
+            "average path length: {:.2} rays",
+            counts.average_path_length()
+        );
+    }
+}
+
+/// formats like 1h02m03s, 4m05s or 6.7s
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds >= 3600 {
+        format!(
+            "{}h{:02}m{:02}s",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    } else if seconds >= 60 {
+        format!("{}m{:02}s", seconds / 60, seconds % 60)
+    } else {
+        format!("{:.1}s", duration.as_secs_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_millis(6_700)), "6.7s");
+        assert_eq!(format_duration(Duration::from_secs(245)), "4m05s");
+        assert_eq!(format_duration(Duration::from_secs(3723)), "1h02m03s");
+    }
+}
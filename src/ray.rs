@@ -1,22 +1,49 @@
+use std::sync::OnceLock;
+
+use crate::counters;
 use crate::hittable::Hittable;
+use crate::sampler::Sampler;
+use crate::spectrum::{self, Emission, RgbSpectrum, SampledSpectrum, Wavelengths};
 use crate::vec3::{Color, Point3, Vec3};
 use crate::world::World;
 
 pub struct Ray {
     pub origin: Point3,
     pub direction: Vec3,
+    /// moment the ray is sent at, in frames, animated objects are hit where they are then
+    pub time: f64,
+    /// None for RGB light, set once the path follows single wavelengths
+    pub wavelengths: Option<Wavelengths>,
 }
 
 impl Ray {
     pub fn new(origin: Point3, direction: Vec3) -> Self {
-        Self { origin, direction }
+        Self::with_time(origin, direction, 0.)
+    }
+
+    pub fn with_time(origin: Point3, direction: Vec3, time: f64) -> Self {
+        Self {
+            origin,
+            direction,
+            time,
+            wavelengths: None,
+        }
+    }
+
+    /// the next ray of the path, sent at the same time with the same wavelengths
+    pub fn scattered(&self, origin: Point3, direction: Vec3) -> Self {
+        Self {
+            origin,
+            direction,
+            ..*self
+        }
     }
 
     pub fn at(&self, t: f64) -> Point3 {
         self.origin + t * self.direction
     }
 
-    pub fn ray_color(&self, world: &World, depth: i32) -> Color {
+    pub fn ray_color(&self, world: &World, depth: i32, sampler: &mut dyn Sampler) -> Color {
         const BLACK: Vec3 = Color {
             x: 0.0,
             y: 0.0,
@@ -27,11 +54,12 @@ impl Ray {
             return BLACK;
         }
 
-        if let Some(record) = world.hit(self, 0.001, std::f64::INFINITY) {
-            if let Some(scatter_data) = record.material.scatter(self, &record) {
+        counters::count_ray();
+        if let Some(record) = world.hit(self, 0.001, f64::INFINITY) {
+            if let Some(scatter_data) = record.material.scatter(self, &record, sampler) {
                 let attenuation = scatter_data.attenuation;
                 let scattered = scatter_data.scattered;
-                return attenuation * Self::ray_color(&scattered, world, depth - 1);
+                return attenuation * Self::ray_color(&scattered, world, depth - 1, sampler);
             }
             return BLACK;
         }
@@ -43,4 +71,46 @@ impl Ray {
         // blendedValue = (1−t)⋅startValue + t⋅endValue
         (1.0 - t) * white + t * Color::new(0.5, 0.7, 1.0)
     }
+
+    /// Like `ray_color`, for a ray that carries `wavelengths`: the light at each of them,
+    /// with the sky shining the light of `sky`.
+    pub fn ray_spectrum(
+        &self,
+        world: &World,
+        sky: &Emission,
+        depth: i32,
+        sampler: &mut dyn Sampler,
+    ) -> SampledSpectrum {
+        static BLUE: OnceLock<RgbSpectrum> = OnceLock::new();
+
+        if depth <= 0 {
+            return SampledSpectrum::constant(0.);
+        }
+        let Some(wavelengths) = self.wavelengths else {
+            return SampledSpectrum::constant(0.);
+        };
+
+        counters::count_ray();
+        if let Some(record) = world.hit(self, 0.001, f64::INFINITY) {
+            if let Some(scatter_data) = record.material.scatter(self, &record, sampler) {
+                let attenuation = scatter_data.spectrum.unwrap_or_else(|| {
+                    SampledSpectrum::from_fn(&wavelengths, |lambda| {
+                        spectrum::interpolate_rgb(scatter_data.attenuation, lambda)
+                    })
+                });
+                let scattered = scatter_data.scattered;
+                return attenuation
+                    * Self::ray_spectrum(&scattered, world, sky, depth - 1, sampler);
+            }
+            return SampledSpectrum::constant(0.);
+        }
+
+        let unit_direction = self.direction.unit_vector();
+        let t = 0.5 * (unit_direction.y + 1.0);
+        // the blend of white and blue of `ray_color` as a reflectance
+        let blue = BLUE.get_or_init(|| RgbSpectrum::fit(Color::new(0.5, 0.7, 1.0)));
+        SampledSpectrum::from_fn(&wavelengths, |lambda| {
+            ((1.0 - t) + t * blue.at(lambda)) * sky.at(lambda)
+        })
+    }
 }
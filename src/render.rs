@@ -0,0 +1,558 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+
+use crate::adaptive::{PixelStatistics, SampleBudget};
+use crate::camera::Camera;
+use crate::counters::{self, RayCounts};
+use crate::error::{Error, Result};
+use crate::film::{Film, Image};
+use crate::filter::{Filter, FilterKind};
+use crate::sampler::{Sampler, SamplerKind};
+use crate::scheduler::{self, Tile, TileOrder, WorkQueues};
+use crate::spectrum::{self, Emission, Illuminant, Wavelengths};
+use crate::vec3::Color;
+use crate::world::World;
+
+/// bounces after which a path is cut off and returns black
+pub const MAX_DEPTH: i32 = 50;
+/// Samples stratified together by renders without a maximum, a stratified sampler
+/// would otherwise spread them over billions of strata.
+pub const UNLIMITED_STRATA: u32 = 16;
+/// side of the tiles `render_image` splits the image into
+const TILE_SIZE: i32 = 32;
+
+/// How samples are taken and filtered, together with the camera and the world this
+/// fully determines the image.
+#[derive(Clone, Copy)]
+pub struct RenderSettings {
+    pub sampler: SamplerKind,
+    pub seed: u64,
+    pub budget: SampleBudget,
+    pub filter: FilterKind,
+    pub filter_radius: f64,
+    /// Trace wavelengths instead of red, green and blue, see `Ray::ray_spectrum`. Glass
+    /// only disperses light this way.
+    pub spectral: bool,
+    /// light of the sky in spectral renders
+    pub illuminant: Illuminant,
+}
+
+impl RenderSettings {
+    fn create_sampler(&self) -> Box<dyn Sampler> {
+        let strata = if self.budget.is_unlimited() {
+            UNLIMITED_STRATA
+        } else {
+            self.budget.max_samples
+        };
+        self.sampler.create(strata, self.seed)
+    }
+}
+
+/// Everything rendered so far, passes keep adding samples to it.
+pub struct Accumulation {
+    pub film: Film,
+    /// statistics of every pixel from the upper left corner to the lower right one
+    pub statistics: Vec<PixelStatistics>,
+    /// rays traced by this process, not kept in checkpoints
+    pub counts: RayCounts,
+}
+
+impl Accumulation {
+    pub fn new(width: i32, height: i32) -> Self {
+        Self {
+            film: Film::new(width, height),
+            statistics: vec![PixelStatistics::default(); (width * height) as usize],
+            counts: RayCounts::default(),
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.film.width()
+    }
+
+    pub fn height(&self) -> i32 {
+        self.film.height()
+    }
+
+    /// average samples taken by the pixels of `area`
+    pub fn samples_per_pixel(&self, area: &Tile) -> f64 {
+        let samples: u64 = area
+            .pixel_indices(self.width())
+            .map(|index| self.statistics[index].count() as u64)
+            .sum();
+        samples as f64 / area.area() as f64
+    }
+
+    /// fewest samples taken by a pixel of `area`
+    pub fn min_samples(&self, area: &Tile) -> u32 {
+        area.pixel_indices(self.width())
+            .map(|index| self.statistics[index].count())
+            .min()
+            .unwrap_or_default()
+    }
+
+    fn add_tile(&mut self, tile: &Tile, result: &TileResult) {
+        self.film.merge(&result.film);
+        self.counts += result.counts;
+        let width = self.width();
+        for (row, statistics) in (tile.y0..).zip(result.statistics.chunks(tile.width as usize)) {
+            let start = (row * width + tile.x0) as usize;
+            self.statistics[start..start + tile.width as usize].copy_from_slice(statistics);
+        }
+    }
+}
+
+/// statistics of the pixels of a tile, row by row
+fn tile_statistics(accumulation: &Accumulation, tile: &Tile) -> Vec<PixelStatistics> {
+    tile.pixel_indices(accumulation.width())
+        .map(|index| accumulation.statistics[index])
+        .collect()
+}
+
+struct TileResult {
+    film: Film,
+    statistics: Vec<PixelStatistics>,
+    counts: RayCounts,
+}
+
+/// Stops a render from another thread, clones share the same flag.
+#[derive(Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+pub type TileCallback<'a> = Box<dyn FnMut(&Tile, &Accumulation) + 'a>;
+
+/// Optional hooks into `render_image`.
+#[derive(Default)]
+pub struct RenderCallbacks<'a> {
+    /// called with every finished tile and everything rendered so far
+    pub on_tile: Option<TileCallback<'a>>,
+    /// called with the fraction of the image that is done, from 0 to 1
+    pub on_progress: Option<Box<dyn FnMut(f64) + 'a>>,
+    /// checked after every tile, a cancelled render returns `Error::Cancelled`
+    pub cancel: Option<CancellationToken>,
+}
+
+/// Renders a `width` x `height` image of the world on all but one core and returns it.
+/// The image holds the light reaching the camera, the exposure of a `PhysicalCamera`
+/// is not applied.
+pub fn render_image(
+    camera: &Arc<dyn Camera>,
+    the_world: &Arc<World>,
+    settings: RenderSettings,
+    width: i32,
+    height: i32,
+    mut callbacks: RenderCallbacks,
+) -> Result<Image> {
+    if callbacks
+        .cancel
+        .as_ref()
+        .is_some_and(CancellationToken::is_cancelled)
+    {
+        return Err(Error::Cancelled);
+    }
+    let tiles = Arc::new(scheduler::tiles(
+        Tile::image(width, height),
+        TILE_SIZE,
+        TileOrder::Spiral,
+    ));
+    let mut accumulation = Accumulation::new(width, height);
+    let mut tiles_done = 0;
+    let mut after_tile = |tile: &Tile, accumulation: &Accumulation| {
+        if let Some(on_tile) = callbacks.on_tile.as_mut() {
+            on_tile(tile, accumulation);
+        }
+        tiles_done += 1;
+        if let Some(on_progress) = callbacks.on_progress.as_mut() {
+            on_progress(tiles_done as f64 / tiles.len() as f64);
+        }
+        match &callbacks.cancel {
+            Some(cancel) if cancel.is_cancelled() => Err(Error::Cancelled),
+            _ => Ok(()),
+        }
+    };
+
+    multiple_threads(
+        camera,
+        the_world,
+        settings,
+        &tiles,
+        &mut accumulation,
+        settings.budget.max_samples,
+        &mut after_tile,
+    )?;
+    Ok(accumulation.film.image())
+}
+
+/// workers used by `multiple_threads`, one core is left to the rest of the system
+pub fn thread_count() -> usize {
+    match ::num_cpus::get() {
+        0..=1 => 1,
+        n => n - 1,
+    }
+}
+
+/// Takes samples of every pixel until it has `sample_end` of them (or converged).
+///
+/// Workers pick tiles through lock-free work stealing and put the rendered ones into
+/// a shared framebuffer. Tiles overlap by the filter radius, they are added to the
+/// accumulation in the order of `tiles` so the sums do not depend on the thread count.
+/// `after_tile` is called with every tile added, the accumulation is then
+/// consistent: every sample counted in the statistics is splatted into the film.
+/// An error returned by `after_tile` stops the render, the workers finish the tiles
+/// they are on and exit.
+pub fn multiple_threads(
+    camera: &Arc<dyn Camera>,
+    the_world: &Arc<World>,
+    settings: RenderSettings,
+    tiles: &Arc<Vec<Tile>>,
+    accumulation: &mut Accumulation,
+    sample_end: u32,
+    after_tile: &mut dyn FnMut(&Tile, &Accumulation) -> Result<()>,
+) -> Result<()> {
+    let thread_count = thread_count();
+    let (width, height) = (accumulation.width(), accumulation.height());
+
+    let queues = Arc::new(WorkQueues::new(tiles.len(), thread_count));
+    let framebuffer: Arc<Vec<Mutex<Option<TileResult>>>> =
+        Arc::new(tiles.iter().map(|_| Mutex::new(None)).collect());
+    let previous_statistics = Arc::new(
+        tiles
+            .iter()
+            .map(|tile| tile_statistics(accumulation, tile))
+            .collect::<Vec<_>>(),
+    );
+    let (done_tx, done_rx) = mpsc::channel::<usize>();
+    let mut threads: Vec<JoinHandle<()>> = Vec::with_capacity(thread_count);
+
+    for worker in 0..thread_count {
+        let the_world = Arc::clone(the_world);
+        let camera = Arc::clone(camera);
+        let tiles = Arc::clone(tiles);
+        let queues = Arc::clone(&queues);
+        let framebuffer = Arc::clone(&framebuffer);
+        let previous_statistics = Arc::clone(&previous_statistics);
+        let done_tx = done_tx.clone();
+
+        threads.push(thread::spawn(move || {
+            let mut sampler = settings.create_sampler();
+            let filter = settings.filter.create(settings.filter_radius);
+            let context = TileContext {
+                camera: camera.as_ref(),
+                the_world: &the_world,
+                filter: filter.as_ref(),
+                budget: settings.budget.limited_to(sample_end),
+                spectral: settings.spectral,
+                sky: Emission::new(settings.illuminant),
+                width,
+                height,
+            };
+
+            while let Some(position) = queues.next(worker) {
+                let mut statistics = previous_statistics[position].clone();
+                let film = context.render_tile(sampler.as_mut(), &mut statistics, &tiles[position]);
+                let Ok(mut slot) = framebuffer[position].lock() else {
+                    return;
+                };
+                *slot = Some(TileResult {
+                    film,
+                    statistics,
+                    counts: counters::take(),
+                });
+                drop(slot);
+                if done_tx.send(position).is_err() {
+                    return;
+                }
+            }
+        }));
+    }
+
+    drop(done_tx);
+
+    let mut cursor = 0;
+    for _ in done_rx {
+        while cursor < tiles.len() {
+            let result = framebuffer[cursor]
+                .lock()
+                .map_err(|_| Error::WorkerPanicked)?
+                .take();
+            let Some(result) = result else {
+                break;
+            };
+            accumulation.add_tile(&tiles[cursor], &result);
+            after_tile(&tiles[cursor], accumulation)?;
+            cursor += 1;
+        }
+    }
+
+    let mut panicked = false;
+    for handle in threads {
+        panicked |= handle.join().is_err();
+    }
+    // every sender is gone, a missing tile means its worker died
+    if panicked || cursor < tiles.len() {
+        return Err(Error::WorkerPanicked);
+    }
+    Ok(())
+}
+
+pub fn single_thread(
+    camera: &dyn Camera,
+    the_world: &World,
+    settings: RenderSettings,
+    tiles: &[Tile],
+    accumulation: &mut Accumulation,
+    sample_end: u32,
+    after_tile: &mut dyn FnMut(&Tile, &Accumulation) -> Result<()>,
+) -> Result<()> {
+    let mut sampler = settings.create_sampler();
+    let filter = settings.filter.create(settings.filter_radius);
+    let context = TileContext {
+        camera,
+        the_world,
+        filter: filter.as_ref(),
+        budget: settings.budget.limited_to(sample_end),
+        spectral: settings.spectral,
+        sky: Emission::new(settings.illuminant),
+        width: accumulation.width(),
+        height: accumulation.height(),
+    };
+
+    for tile in tiles {
+        let mut statistics = tile_statistics(accumulation, tile);
+        let film = context.render_tile(sampler.as_mut(), &mut statistics, tile);
+        let result = TileResult {
+            film,
+            statistics,
+            counts: counters::take(),
+        };
+        accumulation.add_tile(tile, &result);
+        after_tile(tile, accumulation)?;
+    }
+    Ok(())
+}
+
+/// What rendering a tile needs besides the sampler and the statistics of its pixels.
+struct TileContext<'a> {
+    camera: &'a dyn Camera,
+    the_world: &'a World,
+    filter: &'a dyn Filter,
+    budget: SampleBudget,
+    spectral: bool,
+    sky: Emission,
+    /// size of the whole image
+    width: i32,
+    height: i32,
+}
+
+impl TileContext<'_> {
+    /// Renders the pixels of a tile into a film that also covers the neighbouring
+    /// pixels reached by the filter.
+    fn render_tile(
+        &self,
+        sampler: &mut dyn Sampler,
+        statistics: &mut [PixelStatistics],
+        tile: &Tile,
+    ) -> Film {
+        let margin = self.filter.radius().ceil() as i32;
+        let left = (tile.x0 - margin).max(0);
+        let right = (tile.x0 + tile.width - 1 + margin).min(self.width - 1);
+        let top = (tile.y0 - margin).max(0);
+        let bottom = (tile.y0 + tile.height - 1 + margin).min(self.height - 1);
+        let mut film = Film::region(left, top, right - left + 1, bottom - top + 1);
+
+        for row in 0..tile.height {
+            for col in 0..tile.width {
+                let i = tile.x0 + col;
+                // j is counted from the bottom of the image
+                let j = self.height - 1 - (tile.y0 + row);
+                self.calc_color(
+                    sampler,
+                    &mut statistics[(row * tile.width + col) as usize],
+                    (i, j),
+                    |x, y, color| film.add_sample(x, y, color, self.filter),
+                );
+            }
+        }
+
+        film
+    }
+
+    /// Traces samples of pixel (i, j) until the budget says it is done, passing each
+    /// one to `splat` with its position on the film.
+    fn calc_color(
+        &self,
+        sampler: &mut dyn Sampler,
+        statistics: &mut PixelStatistics,
+        (i, j): (i32, i32),
+        mut splat: impl FnMut(f64, f64, Color),
+    ) {
+        while !self.budget.is_done(statistics) {
+            counters::count_primary_ray();
+            sampler.start_pixel_sample(i, j, statistics.count());
+            let (du, dv) = sampler.get_2d();
+            let u = (i as f64 + du) / (self.width - 1) as f64;
+            let v = (j as f64 + dv) / (self.height - 1) as f64;
+
+            let sample = match self.camera.get_ray(u, v, sampler) {
+                Some(mut ray) if self.spectral => {
+                    let wavelengths = Wavelengths::sample(sampler.get_1d());
+                    ray.wavelengths = Some(wavelengths);
+                    let spectrum = ray.ray_spectrum(self.the_world, &self.sky, MAX_DEPTH, sampler);
+                    spectrum::to_rgb(spectrum, &wavelengths)
+                }
+                Some(ray) => ray.ray_color(self.the_world, MAX_DEPTH, sampler),
+                None => Color::origin(),
+            };
+            // film rows go from the top of the image
+            splat(i as f64 + du, (self.height - j) as f64 - dv, sample);
+            statistics.add(sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::ThinLensCamera;
+    use crate::materials::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::vec3::{Point3, Vec3};
+
+    fn scene() -> (Arc<dyn Camera>, Arc<World>) {
+        let camera = ThinLensCamera::new(
+            Point3::new(0., 0., 3.),
+            Point3::origin(),
+            Vec3::new(0., 1., 0.),
+            40.,
+            1.5,
+            0.,
+            3.,
+        );
+        let sphere = Sphere::new(
+            Point3::origin(),
+            1.,
+            Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        );
+        let the_world = World::new(vec![Box::new(sphere)]);
+        (Arc::new(camera), Arc::new(the_world))
+    }
+
+    fn settings() -> RenderSettings {
+        RenderSettings {
+            sampler: SamplerKind::Sobol,
+            seed: 7,
+            budget: SampleBudget::fixed(2),
+            filter: FilterKind::Box,
+            filter_radius: 0.5,
+            spectral: false,
+            illuminant: Illuminant::D65,
+        }
+    }
+
+    #[test]
+    fn test_render_image() {
+        let (camera, the_world) = scene();
+        let mut tiles = 0;
+        let mut progress = 0.;
+        let image = render_image(
+            &camera,
+            &the_world,
+            settings(),
+            48,
+            40,
+            RenderCallbacks {
+                on_tile: Some(Box::new(|_, _| tiles += 1)),
+                on_progress: Some(Box::new(|fraction| progress = fraction)),
+                cancel: None,
+            },
+        )
+        .unwrap();
+
+        assert_eq!((image.width, image.height), (48, 40));
+        assert_eq!(tiles, 2 * 2);
+        assert_eq!(progress, 1.);
+        // the gray sphere in the center is darker than the sky around it
+        let center = image.pixel(24, 20);
+        let corner = image.pixel(0, 0);
+        assert!(center.luminance() > 0.);
+        assert!(center.luminance() < corner.luminance());
+    }
+
+    #[test]
+    fn test_illuminant() {
+        let (camera, the_world) = scene();
+        let sky = |illuminant| {
+            let settings = RenderSettings {
+                spectral: true,
+                illuminant,
+                ..settings()
+            };
+            let image = render_image(
+                &camera,
+                &the_world,
+                settings,
+                16,
+                16,
+                RenderCallbacks::default(),
+            )
+            .unwrap();
+            let corner = image.pixel(0, 0);
+            corner.x / corner.z
+        };
+        // incandescent light makes the sky redder than daylight
+        assert!(sky(Illuminant::A) > 2. * sky(Illuminant::D65));
+    }
+
+    #[test]
+    fn test_unlimited_budget_stratifies_per_pass() {
+        let settings = RenderSettings {
+            sampler: SamplerKind::Stratified,
+            budget: SampleBudget::fixed(crate::adaptive::UNLIMITED_SAMPLES),
+            ..settings()
+        };
+        let mut sampler = settings.create_sampler();
+        let mut strata = [0; UNLIMITED_STRATA as usize];
+        for index in 0..UNLIMITED_STRATA {
+            sampler.start_pixel_sample(3, 4, index);
+            strata[(sampler.get_1d() * UNLIMITED_STRATA as f64) as usize] += 1;
+        }
+        assert_eq!(strata, [1; UNLIMITED_STRATA as usize]);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let (camera, the_world) = scene();
+        let cancel = CancellationToken::new();
+        let token = cancel.clone();
+        let result = render_image(
+            &camera,
+            &the_world,
+            settings(),
+            96,
+            64,
+            RenderCallbacks {
+                on_tile: Some(Box::new(move |_, _| token.cancel())),
+                cancel: Some(cancel),
+                ..RenderCallbacks::default()
+            },
+        );
+        assert!(matches!(result, Err(Error::Cancelled)));
+    }
+}
@@ -0,0 +1,454 @@
+use crate::vec3::Vec3;
+
+/// Source of the random numbers consumed while tracing one sample of a pixel.
+///
+/// Every sample asks for its dimensions in the same order (pixel jitter, lens, then
+/// one or more per bounce), so samplers that know about the sample index can
+/// distribute each dimension well over all samples of the pixel.
+pub trait Sampler {
+    fn start_pixel_sample(&mut self, i: i32, j: i32, sample_index: u32);
+    /// returns a number in [0,1)
+    fn get_1d(&mut self) -> f64;
+    /// returns a point in [0,1)^2
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SamplerKind {
+    /// Independent uniform random numbers
+    Independent,
+    /// Jittered samples in shuffled strata
+    Stratified,
+    /// Cranley-Patterson rotated Halton sequence
+    Halton,
+    /// Owen-scrambled Sobol sequence
+    Sobol,
+}
+
+impl SamplerKind {
+    pub fn create(self, samples_per_pixel: u32, seed: u64) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, seed)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+        }
+    }
+}
+
+/// SplitMix64 stream restarted from (seed, pixel, sample) for every sample, so the
+/// numbers do not depend on which thread renders the pixel or in which order.
+pub struct IndependentSampler {
+    seed: u64,
+    state: u64,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, state: 0 }
+    }
+
+    fn next(&mut self) -> f64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        to_unit(mix_bits(self.state))
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, i: i32, j: i32, sample_index: u32) {
+        self.state = hash(&[self.seed, i as u64, j as u64, sample_index as u64]);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.next()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.next(), self.next())
+    }
+}
+
+/// State shared by the samplers that are driven by the (pixel, sample, dimension) triple.
+struct SampleState {
+    seed: u64,
+    pixel_hash: u64,
+    sample_index: u32,
+    dimension: u64,
+}
+
+impl SampleState {
+    fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel_hash: 0,
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn start(&mut self, i: i32, j: i32, sample_index: u32) {
+        self.pixel_hash = hash(&[self.seed, i as u64, j as u64]);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    /// hash unique for the current pixel and the next dimension
+    fn next_dimension(&mut self) -> u64 {
+        let result = hash(&[self.pixel_hash, self.dimension]);
+        self.dimension += 1;
+        result
+    }
+}
+
+pub struct StratifiedSampler {
+    samples_per_pixel: u32,
+    state: SampleState,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> Self {
+        Self {
+            samples_per_pixel: samples_per_pixel.max(1),
+            state: SampleState::new(seed),
+        }
+    }
+
+    fn jitter(dimension_hash: u64, sample_index: u32, axis: u64) -> f64 {
+        to_unit(hash(&[dimension_hash, sample_index as u64, axis]))
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, i: i32, j: i32, sample_index: u32) {
+        self.state.start(i, j, sample_index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dimension_hash = self.state.next_dimension();
+        let count = self.samples_per_pixel;
+        let index = self.state.sample_index;
+        let stratum = permutation_element(index % count, count, dimension_hash as u32);
+        (stratum as f64 + Self::jitter(dimension_hash, index, 0)) / count as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let dimension_hash = self.state.next_dimension();
+        let x_strata = ((self.samples_per_pixel as f64).sqrt() as u32).max(1);
+        let y_strata = self.samples_per_pixel / x_strata;
+        let count = x_strata * y_strata;
+        let index = self.state.sample_index;
+        let stratum = permutation_element(index % count, count, dimension_hash as u32);
+        let x = (stratum % x_strata) as f64 + Self::jitter(dimension_hash, index, 0);
+        let y = (stratum / x_strata) as f64 + Self::jitter(dimension_hash, index, 1);
+        (
+            (x / x_strata as f64).min(ONE_MINUS_EPSILON),
+            (y / y_strata as f64).min(ONE_MINUS_EPSILON),
+        )
+    }
+}
+
+pub struct HaltonSampler {
+    state: SampleState,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: SampleState::new(seed),
+        }
+    }
+
+    fn sample_dimension(&mut self) -> f64 {
+        let dimension = self.state.dimension as usize;
+        let dimension_hash = self.state.next_dimension();
+        match PRIMES.get(dimension) {
+            Some(&base) => {
+                let value =
+                    radical_inverse(base, self.state.sample_index) + to_unit(dimension_hash);
+                (value - value.floor()).min(ONE_MINUS_EPSILON)
+            }
+            // deep bounces do not get a prime of their own, fall back to hashing
+            None => to_unit(hash(&[dimension_hash, self.state.sample_index as u64])),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, i: i32, j: i32, sample_index: u32) {
+        self.state.start(i, j, sample_index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.sample_dimension()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.sample_dimension(), self.sample_dimension())
+    }
+}
+
+/// Sobol sampler padded from its first two dimensions as described in
+/// "Practical Hash-based Owen Scrambling" (Burley, 2020).
+pub struct SobolSampler {
+    state: SampleState,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: SampleState::new(seed),
+        }
+    }
+
+    fn shuffled_index(&self, dimension_hash: u64) -> u32 {
+        nested_uniform_scramble(self.state.sample_index, dimension_hash as u32)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, i: i32, j: i32, sample_index: u32) {
+        self.state.start(i, j, sample_index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dimension_hash = self.state.next_dimension();
+        let index = self.shuffled_index(dimension_hash);
+        let x = nested_uniform_scramble(sobol(index, 0), (dimension_hash >> 32) as u32);
+        u32_to_unit(x)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let dimension_hash = self.state.next_dimension();
+        let index = self.shuffled_index(dimension_hash);
+        let scramble = hash(&[dimension_hash]);
+        let x = nested_uniform_scramble(sobol(index, 0), scramble as u32);
+        let y = nested_uniform_scramble(sobol(index, 1), (scramble >> 32) as u32);
+        (u32_to_unit(x), u32_to_unit(y))
+    }
+}
+
+const ONE_MINUS_EPSILON: f64 = 1. - f64::EPSILON / 2.;
+
+const PRIMES: [u32; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311,
+];
+
+fn radical_inverse(base: u32, mut index: u32) -> f64 {
+    let inv_base = 1. / base as f64;
+    let mut inv_base_power = 1.;
+    let mut reversed_digits: u64 = 0;
+    while index > 0 {
+        let next = index / base;
+        let digit = index - next * base;
+        reversed_digits = reversed_digits * base as u64 + digit as u64;
+        inv_base_power *= inv_base;
+        index = next;
+    }
+    (reversed_digits as f64 * inv_base_power).min(ONE_MINUS_EPSILON)
+}
+
+/// first two dimensions of the Sobol sequence
+fn sobol(index: u32, dimension: usize) -> u32 {
+    match dimension {
+        0 => index.reverse_bits(),
+        _ => {
+            // direction numbers of the primitive polynomial x + 1
+            let mut direction: u32 = 1 << 31;
+            let mut result = 0;
+            let mut index = index;
+            while index != 0 {
+                if index & 1 == 1 {
+                    result ^= direction;
+                }
+                direction ^= direction >> 1;
+                index >>= 1;
+            }
+            result
+        }
+    }
+}
+
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+/// returns the element at `index` of a random permutation of [0, length),
+/// see "Correlated Multi-Jittered Sampling" (Kensler, 2013)
+fn permutation_element(mut index: u32, length: u32, seed: u32) -> u32 {
+    let mut w = length - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        index ^= seed;
+        index = index.wrapping_mul(0xe170_893d);
+        index ^= seed >> 16;
+        index ^= (index & w) >> 4;
+        index ^= seed >> 8;
+        index = index.wrapping_mul(0x0929_eb3f);
+        index ^= seed >> 23;
+        index ^= (index & w) >> 1;
+        index = index.wrapping_mul(1 | seed >> 27);
+        index = index.wrapping_mul(0x6935_fa69);
+        index ^= (index & w) >> 11;
+        index = index.wrapping_mul(0x74dc_b303);
+        index ^= (index & w) >> 2;
+        index = index.wrapping_mul(0x9e50_1cc3);
+        index ^= (index & w) >> 2;
+        index = index.wrapping_mul(0xc860_a3df);
+        index &= w;
+        index ^= index >> 5;
+        if index < length {
+            break;
+        }
+    }
+    (index.wrapping_add(seed)) % length
+}
+
+/// splitmix64 finalizer
+fn mix_bits(mut value: u64) -> u64 {
+    value ^= value >> 31;
+    value = value.wrapping_mul(0x7fb5_d329_728e_a185);
+    value ^= value >> 27;
+    value = value.wrapping_mul(0x81da_def4_bc2d_d44d);
+    value ^= value >> 33;
+    value
+}
+
+pub fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0x9e37_79b9_7f4a_7c15, |acc, value| {
+        mix_bits(acc ^ value.wrapping_add(0x9e37_79b9_7f4a_7c15))
+    })
+}
+
+fn to_unit(value: u64) -> f64 {
+    // use the upper 53 bits, which is exactly the precision of f64
+    (value >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn u32_to_unit(value: u32) -> f64 {
+    value as f64 / (1u64 << 32) as f64
+}
+
+/// maps a point in [0,1)^2 to the unit disk keeping strata intact
+pub fn sample_unit_disk((u, v): (f64, f64)) -> Vec3 {
+    let offset_x = 2. * u - 1.;
+    let offset_y = 2. * v - 1.;
+    if offset_x == 0. && offset_y == 0. {
+        return Vec3::origin();
+    }
+    let (r, theta) = if offset_x.abs() > offset_y.abs() {
+        (
+            offset_x,
+            std::f64::consts::FRAC_PI_4 * (offset_y / offset_x),
+        )
+    } else {
+        (
+            offset_y,
+            std::f64::consts::FRAC_PI_2 - std::f64::consts::FRAC_PI_4 * (offset_x / offset_y),
+        )
+    };
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.)
+}
+
+/// maps a point in [0,1)^2 to a point on the unit sphere
+pub fn sample_unit_sphere((u, v): (f64, f64)) -> Vec3 {
+    let z = 1. - 2. * u;
+    let r = (1. - z * z).max(0.).sqrt();
+    let phi = 2. * std::f64::consts::PI * v;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// maps a point in [0,1)^3 to a point inside the unit sphere
+pub fn sample_unit_ball(direction: (f64, f64), radius: f64) -> Vec3 {
+    radius.cbrt() * sample_unit_sphere(direction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permutation_element_is_permutation() {
+        let mut seen: Vec<u32> = (0..37)
+            .map(|i| permutation_element(i, 37, 0xdead))
+            .collect();
+        seen.sort_unstable();
+        assert_eq!(seen, (0..37).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn test_radical_inverse() {
+        assert_eq!(radical_inverse(2, 1), 0.5);
+        assert_eq!(radical_inverse(2, 3), 0.75);
+        assert_eq!(radical_inverse(3, 1), 1. / 3.);
+    }
+
+    #[test]
+    fn test_sobol_stratified() {
+        // the first 2^k points of each Sobol dimension fall into distinct intervals
+        for dimension in 0..2 {
+            let mut strata: Vec<u32> = (0..16).map(|i| sobol(i, dimension) >> 28).collect();
+            strata.sort_unstable();
+            assert_eq!(strata, (0..16).collect::<Vec<u32>>());
+        }
+    }
+
+    #[test]
+    fn test_samples_reproducible() {
+        for kind in [
+            SamplerKind::Independent,
+            SamplerKind::Stratified,
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+        ] {
+            let mut first = kind.create(16, 7);
+            let mut second = kind.create(16, 7);
+            // the second sampler visits other pixels before reaching the same sample
+            second.start_pixel_sample(1, 1, 3);
+            second.get_2d();
+            first.start_pixel_sample(4, 2, 9);
+            second.start_pixel_sample(4, 2, 9);
+            for _ in 0..10 {
+                assert_eq!(first.get_1d(), second.get_1d(), "{:?}", kind);
+                assert_eq!(first.get_2d(), second.get_2d(), "{:?}", kind);
+            }
+        }
+    }
+
+    #[test]
+    fn test_samples_in_unit_square() {
+        for kind in [
+            SamplerKind::Independent,
+            SamplerKind::Stratified,
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+        ] {
+            let mut sampler = kind.create(16, 42);
+            for index in 0..16 {
+                sampler.start_pixel_sample(3, 5, index);
+                for _ in 0..100 {
+                    let value = sampler.get_1d();
+                    assert!((0. ..1.).contains(&value), "{:?} {}", kind, value);
+                    let (x, y) = sampler.get_2d();
+                    assert!((0. ..1.).contains(&x), "{:?} {}", kind, x);
+                    assert!((0. ..1.).contains(&y), "{:?} {}", kind, y);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,349 @@
+use std::convert::TryInto;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Rectangle of pixels rendered as one unit of work, y goes from top to bottom.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub x0: i32,
+    pub y0: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Tile {
+    /// the whole image
+    pub fn image(width: i32, height: i32) -> Self {
+        Self {
+            x0: 0,
+            y0: 0,
+            width,
+            height,
+        }
+    }
+
+    pub fn area(&self) -> i64 {
+        self.width as i64 * self.height as i64
+    }
+
+    /// positions of the pixels of the rectangle in an image `image_width` wide,
+    /// row by row
+    pub fn pixel_indices(&self, image_width: i32) -> impl Iterator<Item = usize> + '_ {
+        (self.y0..self.y0 + self.height).flat_map(move |row| {
+            let start = (row * image_width + self.x0) as usize;
+            start..start + self.width as usize
+        })
+    }
+}
+
+/// Part of the image to render, given by its corners `x0,y0,x1,y1` with y going down.
+/// Whole numbers are pixels, numbers with a decimal point are fractions of the image
+/// size, so `0.25,0.25,0.75,0.75` is the center and `100,50,300,0.5` mixes both.
+/// The end corner is exclusive.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CropWindow {
+    corners: [Coordinate; 4],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Coordinate {
+    Pixel(i32),
+    Fraction(f64),
+}
+
+impl Coordinate {
+    fn resolve(self, size: i32) -> i32 {
+        match self {
+            Coordinate::Pixel(pixel) => pixel,
+            Coordinate::Fraction(fraction) => (fraction * size as f64).round() as i32,
+        }
+    }
+}
+
+impl CropWindow {
+    /// the pixels of a `width` x `height` image inside the window, at least one
+    pub fn resolve(&self, width: i32, height: i32) -> Tile {
+        let [x0, y0, x1, y1] = self.corners;
+        let x0 = x0.resolve(width).clamp(0, width - 1);
+        let y0 = y0.resolve(height).clamp(0, height - 1);
+        let x1 = x1.resolve(width).clamp(x0 + 1, width);
+        let y1 = y1.resolve(height).clamp(y0 + 1, height);
+        Tile {
+            x0,
+            y0,
+            width: x1 - x0,
+            height: y1 - y0,
+        }
+    }
+}
+
+impl FromStr for CropWindow {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let coordinates = text
+            .split(',')
+            .map(|value| {
+                let value = value.trim();
+                if value.contains('.') {
+                    match value.parse::<f64>() {
+                        Ok(fraction) if (0. ..=1.).contains(&fraction) => {
+                            Ok(Coordinate::Fraction(fraction))
+                        }
+                        _ => Err(format!("'{}' is not a fraction between 0 and 1", value)),
+                    }
+                } else {
+                    value
+                        .parse::<i32>()
+                        .map(Coordinate::Pixel)
+                        .map_err(|_| format!("'{}' is not a pixel coordinate", value))
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let corners: [Coordinate; 4] = coordinates
+            .try_into()
+            .map_err(|_| "expected four values: x0,y0,x1,y1".to_string())?;
+        Ok(Self { corners })
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CropOutput {
+    /// Only the pixels of the crop window
+    Cropped,
+    /// The whole frame, black outside of the crop window
+    Full,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TileOrder {
+    /// Rows of tiles from top to bottom
+    Scanline,
+    /// Rings of tiles around the center of the image
+    Spiral,
+    /// Along a Hilbert curve, neighbouring tiles are rendered close in time
+    Hilbert,
+}
+
+/// Splits a rectangle of the image into square tiles, cut at the right and bottom
+/// edges, in the order they should be rendered.
+pub fn tiles(area: Tile, size: i32, order: TileOrder) -> Vec<Tile> {
+    let size = size.max(1);
+    let columns = (area.width + size - 1) / size;
+    let rows = (area.height + size - 1) / size;
+    let mut grid: Vec<(i32, i32)> = (0..rows)
+        .flat_map(|row| (0..columns).map(move |column| (column, row)))
+        .collect();
+
+    match order {
+        TileOrder::Scanline => {}
+        TileOrder::Spiral => {
+            let center_x = (columns - 1) as f64 / 2.;
+            let center_y = (rows - 1) as f64 / 2.;
+            let key = |&(column, row): &(i32, i32)| {
+                let dx = column as f64 - center_x;
+                let dy = row as f64 - center_y;
+                (dx.abs().max(dy.abs()), dy.atan2(dx))
+            };
+            grid.sort_by(|a, b| {
+                let (ring_a, angle_a) = key(a);
+                let (ring_b, angle_b) = key(b);
+                ring_a
+                    .total_cmp(&ring_b)
+                    .then_with(|| angle_a.total_cmp(&angle_b))
+            });
+        }
+        TileOrder::Hilbert => {
+            let side = (columns.max(rows) as u32).next_power_of_two();
+            grid.sort_by_key(|&(column, row)| hilbert_index(side, column as u32, row as u32));
+        }
+    }
+
+    grid.into_iter()
+        .map(|(column, row)| Tile {
+            x0: area.x0 + column * size,
+            y0: area.y0 + row * size,
+            width: size.min(area.width - column * size),
+            height: size.min(area.height - row * size),
+        })
+        .collect()
+}
+
+/// distance along the Hilbert curve filling a `side` x `side` square
+fn hilbert_index(side: u32, mut x: u32, mut y: u32) -> u64 {
+    let mut index = 0;
+    let mut s = side / 2;
+    while s > 0 {
+        let rx = (x & s) > 0;
+        let ry = (y & s) > 0;
+        index += (s as u64).pow(2) * ((3 * rx as u64) ^ ry as u64);
+        if !ry {
+            if rx {
+                x = side - 1 - x;
+                y = side - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    index
+}
+
+/// Hands out the positions of work items to a fixed set of workers without locks.
+///
+/// Items are dealt round-robin to one deque per worker, so together the workers follow
+/// the original order. A worker takes from the front of its own deque and, once that
+/// is empty, steals from the back of the others.
+pub struct WorkQueues {
+    queues: Vec<Deque>,
+}
+
+impl WorkQueues {
+    pub fn new(item_count: usize, worker_count: usize) -> Self {
+        let worker_count = worker_count.max(1);
+        let queues = (0..worker_count)
+            .map(|worker| Deque::new((worker..item_count).step_by(worker_count).collect()))
+            .collect();
+        Self { queues }
+    }
+
+    pub fn next(&self, worker: usize) -> Option<usize> {
+        let count = self.queues.len();
+        self.queues[worker % count].pop_front().or_else(|| {
+            (1..count)
+                .map(|offset| &self.queues[(worker + offset) % count])
+                .find_map(Deque::steal_back)
+        })
+    }
+}
+
+/// Fixed list of items with the bounds of the remaining ones packed into one atomic:
+/// head in the low 32 bits and tail in the high ones.
+struct Deque {
+    items: Vec<usize>,
+    bounds: AtomicU64,
+}
+
+impl Deque {
+    fn new(items: Vec<usize>) -> Self {
+        let bounds = AtomicU64::new(pack(0, items.len() as u32));
+        Self { items, bounds }
+    }
+
+    fn pop_front(&self) -> Option<usize> {
+        self.take(|head, tail| (head + 1, tail, head))
+    }
+
+    fn steal_back(&self) -> Option<usize> {
+        self.take(|head, tail| (head, tail - 1, tail - 1))
+    }
+
+    /// `shrink` maps the current bounds to the new ones and the taken position
+    fn take(&self, shrink: impl Fn(u32, u32) -> (u32, u32, u32)) -> Option<usize> {
+        let mut current = self.bounds.load(Ordering::Acquire);
+        loop {
+            let (head, tail) = unpack(current);
+            if head >= tail {
+                return None;
+            }
+            let (new_head, new_tail, position) = shrink(head, tail);
+            match self.bounds.compare_exchange_weak(
+                current,
+                pack(new_head, new_tail),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return Some(self.items[position as usize]),
+                Err(actual) => current = actual,
+            }
+        }
+    }
+}
+
+fn pack(head: u32, tail: u32) -> u64 {
+    head as u64 | (tail as u64) << 32
+}
+
+fn unpack(bounds: u64) -> (u32, u32) {
+    (bounds as u32, (bounds >> 32) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_tiles_cover_image() {
+        for order in [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+            let tiles = tiles(Tile::image(100, 70), 32, order);
+            assert_eq!(tiles.len(), 4 * 3);
+            let area: i32 = tiles.iter().map(|tile| tile.width * tile.height).sum();
+            assert_eq!(area, 100 * 70, "{:?}", order);
+        }
+    }
+
+    #[test]
+    fn test_spiral_starts_in_center() {
+        let tiles = tiles(Tile::image(96, 96), 32, TileOrder::Spiral);
+        assert_eq!((tiles[0].x0, tiles[0].y0), (32, 32));
+    }
+
+    #[test]
+    fn test_hilbert_neighbours() {
+        let tiles = tiles(Tile::image(128, 128), 16, TileOrder::Hilbert);
+        for pair in tiles.windows(2) {
+            let distance = (pair[0].x0 - pair[1].x0).abs() + (pair[0].y0 - pair[1].y0).abs();
+            assert_eq!(distance, 16);
+        }
+    }
+
+    #[test]
+    fn test_crop_window() {
+        let crop: CropWindow = "0.25,0,0.75,0.5".parse().unwrap();
+        let area = crop.resolve(200, 100);
+        assert_eq!(
+            (area.x0, area.y0, area.width, area.height),
+            (50, 0, 100, 50)
+        );
+
+        let crop: CropWindow = "10, 20, 30, 1.0".parse().unwrap();
+        let area = crop.resolve(200, 100);
+        assert_eq!(
+            (area.x0, area.y0, area.width, area.height),
+            (10, 20, 20, 80)
+        );
+
+        let tiles = tiles(area, 16, TileOrder::Scanline);
+        assert_eq!((tiles[0].x0, tiles[0].y0), (10, 20));
+        let covered: i64 = tiles.iter().map(Tile::area).sum();
+        assert_eq!(covered, area.area());
+
+        assert!("1,2,3".parse::<CropWindow>().is_err());
+        assert!("0,0,1.5,1".parse::<CropWindow>().is_err());
+    }
+
+    #[test]
+    fn test_every_item_taken_once() {
+        let queues = Arc::new(WorkQueues::new(1000, 4));
+        let handles: Vec<_> = (0..4)
+            .map(|worker| {
+                let queues = Arc::clone(&queues);
+                thread::spawn(move || {
+                    let mut taken = vec![];
+                    while let Some(item) = queues.next(worker) {
+                        taken.push(item);
+                    }
+                    taken
+                })
+            })
+            .collect();
+        let mut taken: Vec<usize> = handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+        taken.sort_unstable();
+        assert_eq!(taken, (0..1000).collect::<Vec<usize>>());
+    }
+}
@@ -0,0 +1,583 @@
+use std::ops;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::vec3::Color;
+
+/// Shortest wavelength sampled, in nanometers.
+pub const LAMBDA_MIN: f64 = 380.;
+/// Longest wavelength sampled, in nanometers.
+pub const LAMBDA_MAX: f64 = 730.;
+/// Wavelengths traced together by one path.
+pub const WAVELENGTHS: usize = 4;
+
+/// Wavelengths carried by a path, sampled with hero wavelength sampling (Wilkie et al.
+/// 2014): the hero is sampled uniformly and the others are spread evenly over the
+/// range from it. Events that depend on the wavelength, like dispersion, can only
+/// follow the hero, the others are then dropped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Wavelengths {
+    lambda: [f64; WAVELENGTHS],
+    /// how many of `lambda` are still carried, the hero first
+    count: usize,
+}
+
+impl Wavelengths {
+    /// `u` in [0, 1) places the hero
+    pub fn sample(u: f64) -> Self {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let mut lambda = [0.; WAVELENGTHS];
+        for (index, value) in lambda.iter_mut().enumerate() {
+            let offset = (u + index as f64 / WAVELENGTHS as f64).fract();
+            *value = LAMBDA_MIN + offset * range;
+        }
+        Self {
+            lambda,
+            count: WAVELENGTHS,
+        }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    /// wavelengths still carried, the hero first
+    pub fn lambda(&self) -> &[f64] {
+        &self.lambda[..self.count]
+    }
+
+    pub fn is_secondary_terminated(&self) -> bool {
+        self.count == 1
+    }
+
+    /// Keeps only the hero. Its value then stands for all the wavelengths sampled
+    /// with it, see `SampledSpectrum::terminate_secondary`.
+    pub fn terminate_secondary(&mut self) {
+        self.count = 1;
+    }
+
+    /// probability density of every wavelength
+    pub fn pdf(&self) -> f64 {
+        1. / (LAMBDA_MAX - LAMBDA_MIN)
+    }
+}
+
+/// Values of a spectrum at the `Wavelengths` of a path, dropped wavelengths are 0.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SampledSpectrum([f64; WAVELENGTHS]);
+
+impl SampledSpectrum {
+    pub fn constant(value: f64) -> Self {
+        Self([value; WAVELENGTHS])
+    }
+
+    /// `f` evaluated at each of the wavelengths, including dropped ones
+    pub fn from_fn(wavelengths: &Wavelengths, f: impl Fn(f64) -> f64) -> Self {
+        Self(wavelengths.lambda.map(f))
+    }
+
+    pub fn values(&self) -> &[f64; WAVELENGTHS] {
+        &self.0
+    }
+
+    /// Keeps the value of the hero only, scaled to stand for all wavelengths as the
+    /// image averages over them.
+    pub fn terminate_secondary(self) -> Self {
+        let mut values = [0.; WAVELENGTHS];
+        values[0] = self.0[0] * WAVELENGTHS as f64;
+        Self(values)
+    }
+}
+
+impl ops::Mul for SampledSpectrum {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self(std::array::from_fn(|index| self.0[index] * other.0[index]))
+    }
+}
+
+impl ops::Mul<f64> for SampledSpectrum {
+    type Output = Self;
+
+    fn mul(self, factor: f64) -> Self {
+        Self(self.0.map(|value| value * factor))
+    }
+}
+
+impl ops::Add for SampledSpectrum {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self(std::array::from_fn(|index| self.0[index] + other.0[index]))
+    }
+}
+
+/// Gaussian with different widths left and right of the mean
+fn lobe(lambda: f64, mean: f64, left: f64, right: f64) -> f64 {
+    let sigma = if lambda < mean { left } else { right };
+    let x = (lambda - mean) / sigma;
+    (-0.5 * x * x).exp()
+}
+
+/// CIE 1931 color matching functions at `lambda` in nanometers, the multi-lobe fit of
+/// Wyman, Sloan and Shirley, "Simple Analytic Approximations to the CIE XYZ Color
+/// Matching Functions", 2013.
+pub fn xyz_matching(lambda: f64) -> Color {
+    Color::new(
+        1.056 * lobe(lambda, 599.8, 37.9, 31.0) + 0.362 * lobe(lambda, 442.0, 16.0, 26.7)
+            - 0.065 * lobe(lambda, 501.1, 20.4, 26.2),
+        0.821 * lobe(lambda, 568.8, 46.9, 40.5) + 0.286 * lobe(lambda, 530.9, 16.3, 31.1),
+        1.217 * lobe(lambda, 437.0, 11.8, 36.0) + 0.681 * lobe(lambda, 459.0, 26.0, 13.8),
+    )
+}
+
+/// linear sRGB with the D65 white point
+pub fn xyz_to_rgb(xyz: Color) -> Color {
+    Color::new(
+        3.2404542 * xyz.x - 1.5371385 * xyz.y - 0.4985314 * xyz.z,
+        -0.9692660 * xyz.x + 1.8760108 * xyz.y + 0.0415560 * xyz.z,
+        0.0556434 * xyz.x - 0.2040259 * xyz.y + 1.0572252 * xyz.z,
+    )
+}
+
+/// CIE standard illuminant D65, average daylight, from 380 to 730 nm in steps of 10
+const D65: [f64; 36] = [
+    49.9755, 54.6482, 82.7549, 91.486, 93.4318, 86.6823, 104.865, 117.008, 117.812, 114.861,
+    115.923, 108.811, 109.354, 107.802, 104.79, 107.689, 104.405, 104.046, 100., 96.3342, 95.788,
+    88.6856, 90.0062, 89.5991, 87.6987, 83.2886, 83.6992, 80.0268, 80.2146, 82.2778, 78.2842,
+    69.7213, 71.6091, 74.349, 61.604, 69.8856,
+];
+
+/// Spectrum of a light source, its brightness does not matter, see `Emission`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Illuminant {
+    /// CIE D65, average daylight, white in sRGB
+    D65,
+    /// CIE A, incandescent light
+    A,
+    /// equal energy at every wavelength
+    E,
+    /// black body at a temperature in kelvin, see `Illuminant::blackbody`
+    Blackbody(f64),
+}
+
+/// Range of black body temperatures in kelvin, colder ones hardly glow in the visible
+/// range and their spectrum underflows.
+pub const BLACKBODY_TEMPERATURES: std::ops::RangeInclusive<f64> = 500.0..=100_000.0;
+
+impl Illuminant {
+    /// None outside of `BLACKBODY_TEMPERATURES`
+    pub fn blackbody(temperature: f64) -> Option<Self> {
+        BLACKBODY_TEMPERATURES
+            .contains(&temperature)
+            .then_some(Illuminant::Blackbody(temperature))
+    }
+
+    /// relative power at `lambda` in nanometers
+    pub fn power(&self, lambda: f64) -> f64 {
+        match self {
+            Illuminant::D65 => {
+                let position = ((lambda - 380.) / 10.).clamp(0., (D65.len() - 1) as f64);
+                let index = (position as usize).min(D65.len() - 2);
+                let t = position - index as f64;
+                D65[index] * (1. - t) + D65[index + 1] * t
+            }
+            Illuminant::A => planck(lambda, 2856.),
+            Illuminant::E => 1.,
+            Illuminant::Blackbody(temperature) => planck(lambda, *temperature),
+        }
+    }
+}
+
+impl FromStr for Illuminant {
+    type Err = String;
+
+    /// d65, a, e or a temperature like 3200K
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.to_ascii_lowercase().as_str() {
+            "d65" => Ok(Illuminant::D65),
+            "a" => Ok(Illuminant::A),
+            "e" => Ok(Illuminant::E),
+            kelvin => kelvin
+                .strip_suffix('k')
+                .and_then(|temperature| temperature.parse::<f64>().ok())
+                .and_then(Illuminant::blackbody)
+                .ok_or_else(|| {
+                    format!(
+                        "'{}' is not d65, a, e or a temperature from {}K to {}K",
+                        text,
+                        BLACKBODY_TEMPERATURES.start(),
+                        BLACKBODY_TEMPERATURES.end()
+                    )
+                }),
+        }
+    }
+}
+
+/// Planck's law, spectral radiance of a black body at `lambda` in nanometers
+fn planck(lambda: f64, temperature: f64) -> f64 {
+    const C: f64 = 299_792_458.;
+    const H: f64 = 6.626_070_15e-34;
+    const KB: f64 = 1.380_649e-23;
+    let meters = lambda * 1e-9;
+    2. * H * C * C / (meters.powi(5) * ((H * C / (meters * KB * temperature)).exp() - 1.))
+}
+
+/// An illuminant scaled so that a white surface lit by it has the luminance 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Emission {
+    illuminant: Illuminant,
+    scale: f64,
+}
+
+impl Emission {
+    pub fn new(illuminant: Illuminant) -> Self {
+        let luminance = integrate(|lambda| xyz_matching(lambda) * illuminant.power(lambda)).y;
+        Self {
+            illuminant,
+            scale: 1. / luminance,
+        }
+    }
+
+    pub fn at(&self, lambda: f64) -> f64 {
+        self.illuminant.power(lambda) * self.scale
+    }
+}
+
+/// Integrates `f` over the sampled range with the midpoint rule, one step per nm.
+fn integrate(f: impl Fn(f64) -> Color) -> Color {
+    let steps = (LAMBDA_MAX - LAMBDA_MIN) as usize;
+    let step = (LAMBDA_MAX - LAMBDA_MIN) / steps as f64;
+    let mut sum = Color::origin();
+    for index in 0..steps {
+        sum += f(LAMBDA_MIN + (index as f64 + 0.5) * step) * step;
+    }
+    sum
+}
+
+/// Linear sRGB seen for each nm of the range in D65 light, scaled so that they add up
+/// to white. The image of a spectrum is its values weighted with these.
+fn rgb_table() -> &'static [Color] {
+    static TABLE: OnceLock<Vec<Color>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let d65 = Emission::new(Illuminant::D65);
+        let steps = (LAMBDA_MAX - LAMBDA_MIN) as usize;
+        let table: Vec<Color> = (0..steps)
+            .map(|index| {
+                let lambda = LAMBDA_MIN + index as f64 + 0.5;
+                xyz_to_rgb(xyz_matching(lambda) * d65.at(lambda))
+            })
+            .collect();
+        let white = table.iter().fold(Color::origin(), |sum, &rgb| sum + rgb);
+        table
+            .into_iter()
+            .map(|rgb| Color::new(rgb.x / white.x, rgb.y / white.y, rgb.z / white.z))
+            .collect()
+    })
+}
+
+/// Linear sRGB of the light with the values `spectrum` at `wavelengths` (the ones
+/// sampled at the camera), in units where D65 light of luminance 1 is white.
+pub fn to_rgb(spectrum: SampledSpectrum, wavelengths: &Wavelengths) -> Color {
+    static WHITE: OnceLock<Color> = OnceLock::new();
+    let white = WHITE.get_or_init(|| {
+        let d65 = Emission::new(Illuminant::D65);
+        xyz_to_rgb(integrate(|lambda| xyz_matching(lambda) * d65.at(lambda)))
+    });
+    let mut xyz = Color::origin();
+    for (value, lambda) in spectrum.0.iter().zip(wavelengths.lambda) {
+        xyz += xyz_matching(lambda) * (value / wavelengths.pdf());
+    }
+    let rgb = xyz_to_rgb(xyz / WAVELENGTHS as f64);
+    Color::new(rgb.x / white.x, rgb.y / white.y, rgb.z / white.z)
+}
+
+/// Reflectance spectrum with the sRGB color of an RGB value in D65 light, smooth and
+/// between 0 and 1: a sigmoid of a quadratic polynomial (Jakob and Hanika, "A
+/// Low-Dimensional Function Space for Efficient Spectral Upsampling", 2019). Values
+/// above 1 scale a spectrum fitted to their brightest channel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RgbSpectrum {
+    coefficients: [f64; 3],
+    scale: f64,
+}
+
+impl RgbSpectrum {
+    /// Fits the coefficients with Newton's method, moving the target step by step
+    /// from gray, where all coefficients are 0, to `rgb`.
+    pub fn fit(rgb: Color) -> Self {
+        let brightest = rgb.x.max(rgb.y).max(rgb.z);
+        let scale = brightest.max(1.);
+        // black and white would need infinite coefficients
+        let channel = |value: f64| (value / scale).clamp(1e-3, 1. - 1e-3);
+        let target = Color::new(channel(rgb.x), channel(rgb.y), channel(rgb.z));
+        let gray = Color::new(0.5, 0.5, 0.5);
+
+        let table = rgb_table();
+        let mut coefficients = [0.; 3];
+        const STEPS: usize = 8;
+        for step in 1..=STEPS {
+            let goal = gray + (target - gray) * (step as f64 / STEPS as f64);
+            for _ in 0..8 {
+                let mut rgb = Color::origin();
+                let mut jacobian = [Color::origin(); 3];
+                for (index, weight) in table.iter().enumerate() {
+                    let t = (index as f64 + 0.5) / table.len() as f64;
+                    let x = polynomial(&coefficients, t);
+                    let root = (1. + x * x).sqrt();
+                    rgb += *weight * (0.5 + x / (2. * root));
+                    let slope = 1. / (2. * root * root * root);
+                    for (column, power) in jacobian.iter_mut().zip([t * t, t, 1.]) {
+                        *column += *weight * (slope * power);
+                    }
+                }
+                let residual = rgb - goal;
+                if residual.length() < 1e-6 {
+                    break;
+                }
+                let Some(step) = solve(jacobian, -residual) else {
+                    break;
+                };
+                for (coefficient, delta) in coefficients.iter_mut().zip(step) {
+                    *coefficient += delta;
+                }
+            }
+        }
+        Self {
+            coefficients,
+            scale,
+        }
+    }
+
+    pub fn at(&self, lambda: f64) -> f64 {
+        let t = (lambda - LAMBDA_MIN) / (LAMBDA_MAX - LAMBDA_MIN);
+        let x = polynomial(&self.coefficients, t);
+        self.scale * (0.5 + x / (2. * (1. + x * x).sqrt()))
+    }
+
+    pub fn sample(&self, wavelengths: &Wavelengths) -> SampledSpectrum {
+        SampledSpectrum::from_fn(wavelengths, |lambda| self.at(lambda))
+    }
+}
+
+fn polynomial(coefficients: &[f64; 3], t: f64) -> f64 {
+    (coefficients[0] * t + coefficients[1]) * t + coefficients[2]
+}
+
+/// solves the 3x3 system with the given columns by Cramer's rule
+fn solve(columns: [Color; 3], right: Color) -> Option<[f64; 3]> {
+    let determinant = |a: Color, b: Color, c: Color| a.dot(&b.cross(&c));
+    let full = determinant(columns[0], columns[1], columns[2]);
+    if full.abs() < 1e-12 {
+        return None;
+    }
+    Some([
+        determinant(right, columns[1], columns[2]) / full,
+        determinant(columns[0], right, columns[2]) / full,
+        determinant(columns[0], columns[1], right) / full,
+    ])
+}
+
+/// Value at `lambda` of a physical quantity known at the red, green and blue
+/// wavelengths of sRGB, like an index of refraction, interpolated linearly between
+/// them and constant beyond.
+pub fn interpolate_rgb(value: Color, lambda: f64) -> f64 {
+    const BLUE: f64 = 465.;
+    const GREEN: f64 = 550.;
+    const RED: f64 = 630.;
+    if lambda <= BLUE {
+        value.z
+    } else if lambda <= GREEN {
+        value.z + (value.y - value.z) * (lambda - BLUE) / (GREEN - BLUE)
+    } else if lambda <= RED {
+        value.y + (value.x - value.y) * (lambda - GREEN) / (RED - GREEN)
+    } else {
+        value.x
+    }
+}
+
+/// Index of refraction changing with the wavelength, what splits white light into a
+/// rainbow. Wavelengths are in micrometers in the formulas.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dispersion {
+    /// n = a + b / lambda^2
+    Cauchy { a: f64, b: f64 },
+    /// n^2 = 1 + sum of b lambda^2 / (lambda^2 - c)
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+/// Wavelengths of the Fraunhofer lines the Abbe number is defined with, in micrometers.
+const LINE_D: f64 = 0.5876;
+const LINE_F: f64 = 0.4861;
+const LINE_C: f64 = 0.6563;
+
+impl Dispersion {
+    /// The Cauchy fit of a glass with the index `n_d` at the yellow helium line and
+    /// the Abbe number `abbe`, lower numbers spread the colors wider.
+    pub fn from_abbe(n_d: f64, abbe: f64) -> Self {
+        let spread = (n_d - 1.) / abbe;
+        let b = spread / (1. / (LINE_F * LINE_F) - 1. / (LINE_C * LINE_C));
+        Dispersion::Cauchy {
+            a: n_d - b / (LINE_D * LINE_D),
+            b,
+        }
+    }
+
+    /// index of refraction at the yellow helium line, what a glass is usually listed with
+    pub fn n_d(&self) -> f64 {
+        self.ior(LINE_D * 1000.)
+    }
+
+    /// index of refraction at `lambda` in nanometers
+    pub fn ior(&self, lambda: f64) -> f64 {
+        let micrometers = lambda / 1000.;
+        let l2 = micrometers * micrometers;
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / l2,
+            Dispersion::Sellmeier { b, c } => {
+                let sum: f64 = b.iter().zip(c).map(|(b, c)| b * l2 / (l2 - c)).sum();
+                (1. + sum).sqrt()
+            }
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DispersionPreset {
+    /// Borosilicate crown glass, the most common optical glass
+    Bk7,
+    /// Fused silica, weakly dispersive
+    FusedSilica,
+    /// Dense flint glass, strongly dispersive
+    Sf11,
+    /// Diamond, high index and strong fire
+    Diamond,
+}
+
+impl DispersionPreset {
+    /// Sellmeier coefficients from the published fits of each material
+    pub fn dispersion(self) -> Dispersion {
+        let (b, c) = match self {
+            DispersionPreset::Bk7 => (
+                [1.03961212, 0.231792344, 1.01046945],
+                [0.00600069867, 0.0200179144, 103.560653],
+            ),
+            DispersionPreset::FusedSilica => (
+                [0.6961663, 0.4079426, 0.8974794],
+                [0.0046791, 0.0135121, 97.934003],
+            ),
+            DispersionPreset::Sf11 => (
+                [1.73759695, 0.313747346, 1.89878101],
+                [0.013188707, 0.0623068142, 155.23629],
+            ),
+            DispersionPreset::Diamond => ([0.3306, 4.3356, 0.], [0.030625, 0.011236, 0.]),
+        };
+        Dispersion::Sellmeier { b, c }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hero_wavelengths_spread() {
+        let wavelengths = Wavelengths::sample(0.9);
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        assert!((wavelengths.hero() - (LAMBDA_MIN + 0.9 * range)).abs() < 1e-9);
+        let mut sorted = wavelengths.lambda().to_vec();
+        sorted.sort_by(f64::total_cmp);
+        for pair in sorted.windows(2) {
+            assert!((pair[1] - pair[0] - range / 4.).abs() < 1e-9);
+        }
+        let mut hero = wavelengths;
+        hero.terminate_secondary();
+        assert_eq!(hero.lambda(), &[wavelengths.hero()]);
+    }
+
+    #[test]
+    fn test_upsampled_colors_round_trip() {
+        for rgb in [
+            Color::new(0.5, 0.5, 0.5),
+            Color::new(0.4, 0.2, 0.1),
+            Color::new(0.1, 0.7, 0.3),
+            Color::new(0.2, 0.3, 0.9),
+            Color::new(2., 1., 0.5),
+        ] {
+            let spectrum = RgbSpectrum::fit(rgb);
+            let d65 = Emission::new(Illuminant::D65);
+            let back =
+                integrate(|lambda| xyz_matching(lambda) * (spectrum.at(lambda) * d65.at(lambda)));
+            let white = xyz_to_rgb(integrate(|lambda| xyz_matching(lambda) * d65.at(lambda)));
+            let back = xyz_to_rgb(back);
+            let back = Color::new(back.x / white.x, back.y / white.y, back.z / white.z);
+            // channels are kept just below the brightest possible
+            let tolerance = 2e-3 * rgb.x.max(1.);
+            assert!((back - rgb).length() < tolerance, "{} became {}", rgb, back);
+            for lambda in [LAMBDA_MIN, 500., LAMBDA_MAX] {
+                assert!(spectrum.at(lambda) >= 0. && spectrum.at(lambda) <= rgb.x.max(1.));
+            }
+        }
+    }
+
+    #[test]
+    fn test_white_light_is_white() {
+        // a constant spectrum of D65 light averaged over hero wavelengths
+        let d65 = Emission::new(Illuminant::D65);
+        let mut sum = Color::origin();
+        let count = 1000;
+        for index in 0..count {
+            let wavelengths = Wavelengths::sample((index as f64 + 0.5) / count as f64);
+            sum += to_rgb(
+                SampledSpectrum::from_fn(&wavelengths, |lambda| d65.at(lambda)),
+                &wavelengths,
+            );
+        }
+        let mean = sum / count as f64;
+        assert!((mean - Color::new(1., 1., 1.)).length() < 1e-2, "{}", mean);
+
+        let warm: Illuminant = "2700K".parse().unwrap();
+        assert_eq!(warm, Illuminant::Blackbody(2700.));
+        assert!(warm.power(650.) > warm.power(450.));
+        assert!("warm".parse::<Illuminant>().is_err());
+        for temperature in ["10K", "0K", "-300K", "infK", "NaNK", "1e6K"] {
+            assert!(
+                temperature.parse::<Illuminant>().is_err(),
+                "{}",
+                temperature
+            );
+        }
+        // the extremes still give a finite sky
+        for temperature in [
+            *BLACKBODY_TEMPERATURES.start(),
+            *BLACKBODY_TEMPERATURES.end(),
+        ] {
+            let emission = Emission::new(Illuminant::blackbody(temperature).unwrap());
+            for lambda in [LAMBDA_MIN, 550., LAMBDA_MAX] {
+                assert!(
+                    emission.at(lambda).is_finite(),
+                    "{} {}",
+                    temperature,
+                    lambda
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_dispersion() {
+        let bk7 = DispersionPreset::Bk7.dispersion();
+        assert!((bk7.ior(587.6) - 1.5168).abs() < 1e-4);
+        // blue is bent more than red
+        assert!(bk7.ior(450.) > bk7.ior(650.));
+        let diamond = DispersionPreset::Diamond.dispersion();
+        assert!((diamond.ior(589.3) - 2.417).abs() < 2e-3);
+
+        let cauchy = Dispersion::from_abbe(1.5, 40.);
+        assert!((cauchy.ior(587.6) - 1.5).abs() < 1e-12);
+        assert!((cauchy.n_d() - 1.5).abs() < 1e-12);
+        let abbe = (cauchy.ior(587.6) - 1.) / (cauchy.ior(486.1) - cauchy.ior(656.3));
+        assert!((abbe - 40.).abs() < 1e-9);
+    }
+}
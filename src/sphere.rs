@@ -22,7 +22,7 @@ impl Sphere {
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray, min: f64, max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, min: f64, max: f64) -> Option<HitRecord<'_>> {
         let oc = ray.origin - self.center;
         let a = ray.direction.length_squared();
         let half_b = oc.dot(&ray.direction);
@@ -60,4 +60,8 @@ impl Hittable for Sphere {
             material: &*self.material,
         })
     }
+
+    fn center(&self, _time: f64) -> Option<Point3> {
+        Some(self.center)
+    }
 }
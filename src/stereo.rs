@@ -0,0 +1,207 @@
+use crate::camera::{Camera, Frame};
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec3::Vec3;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StereoLayout {
+    /// Left eye in the left half of the image, right eye in the right half
+    SideBySide,
+    /// Left eye in the top half of the image, right eye in the bottom half
+    TopBottom,
+}
+
+impl StereoLayout {
+    /// aspect ratio of the view of one eye in an image of `aspect_ratio`
+    pub fn eye_aspect_ratio(self, aspect_ratio: f64) -> f64 {
+        match self {
+            StereoLayout::SideBySide => aspect_ratio / 2.,
+            StereoLayout::TopBottom => aspect_ratio * 2.,
+        }
+    }
+
+    /// aspect ratio of an image holding two views of `eye_aspect_ratio`
+    pub fn image_aspect_ratio(self, eye_aspect_ratio: f64) -> f64 {
+        match self {
+            StereoLayout::SideBySide => eye_aspect_ratio * 2.,
+            StereoLayout::TopBottom => eye_aspect_ratio / 2.,
+        }
+    }
+
+    /// side of the eye seeing (u, v) of the image, -1 for the left one and 1 for the
+    /// right one, and the same point in the view of that eye
+    fn split(self, u: f64, v: f64) -> (f64, f64, f64) {
+        match self {
+            StereoLayout::SideBySide if u < 0.5 => (-1., 2. * u, v),
+            StereoLayout::SideBySide => (1., 2. * u - 1., v),
+            StereoLayout::TopBottom if v >= 0.5 => (-1., u, 2. * v - 1.),
+            StereoLayout::TopBottom => (1., u, 2. * v),
+        }
+    }
+}
+
+/// Two eyes `ipd` apart rendered into one image.
+///
+/// Rays of the camera between the eyes are moved to the eye and turned to meet at the
+/// same point `convergence` away, objects at that distance have no parallax.
+pub struct StereoCamera {
+    camera: Box<dyn Camera>,
+    frame: Frame,
+    layout: StereoLayout,
+    half_ipd: f64,
+    convergence: f64,
+    omnidirectional: bool,
+}
+
+impl StereoCamera {
+    /// `camera` sees from between the eyes with the aspect ratio of one of them, see
+    /// `StereoLayout::eye_aspect_ratio`. The eyes sit along `frame.u` and converge on
+    /// the plane `convergence` in front of them.
+    pub fn new(
+        camera: Box<dyn Camera>,
+        frame: Frame,
+        layout: StereoLayout,
+        ipd: f64,
+        convergence: f64,
+    ) -> Self {
+        Self {
+            camera,
+            frame,
+            layout,
+            half_ipd: ipd / 2.,
+            convergence,
+            omnidirectional: false,
+        }
+    }
+
+    /// Omni-directional stereo for panoramas: the eyes turn with the direction of
+    /// every ray around the vertical axis, so any part of the panorama can be looked at.
+    /// They converge on the sphere of radius `convergence`.
+    pub fn omnidirectional(
+        camera: Box<dyn Camera>,
+        frame: Frame,
+        layout: StereoLayout,
+        ipd: f64,
+        convergence: f64,
+    ) -> Self {
+        Self {
+            omnidirectional: true,
+            ..Self::new(camera, frame, layout, ipd, convergence)
+        }
+    }
+
+    /// direction from the left eye to the right one for a ray along `direction`
+    fn baseline(&self, direction: Vec3) -> Vec3 {
+        if !self.omnidirectional {
+            return self.frame.u;
+        }
+        let right = direction.dot(&self.frame.u);
+        let forward = -direction.dot(&self.frame.w);
+        let horizontal = (right * right + forward * forward).sqrt();
+        if horizontal < 1e-9 {
+            // straight up or down both eyes see the same
+            return Vec3::origin();
+        }
+        self.frame
+            .to_world(forward / horizontal, 0., -right / horizontal)
+    }
+}
+
+impl Camera for StereoCamera {
+    fn get_ray(&self, u: f64, v: f64, sampler: &mut dyn Sampler) -> Option<Ray> {
+        let (side, u, v) = self.layout.split(u, v);
+        let ray = self.camera.get_ray(u, v, sampler)?;
+        let direction = ray.direction.unit_vector();
+        let eye = ray.origin + side * self.half_ipd * self.baseline(direction);
+        if !self.convergence.is_finite() {
+            return Some(Ray::with_time(eye, direction, ray.time));
+        }
+
+        let along_axis = -direction.dot(&self.frame.w);
+        let distance = if self.omnidirectional || along_axis <= 0. {
+            self.convergence
+        } else {
+            self.convergence / along_axis
+        };
+        Some(Ray::with_time(
+            eye,
+            ray.origin + distance * direction - eye,
+            ray.time,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::{EquirectangularCamera, ThinLensCamera};
+    use crate::sampler::SamplerKind;
+    use crate::vec3::Point3;
+
+    fn frame() -> (Point3, Point3, Vec3) {
+        (
+            Point3::new(0., 0., 5.),
+            Point3::origin(),
+            Vec3::new(0., 1., 0.),
+        )
+    }
+
+    #[test]
+    fn test_image_aspect_ratio_holds_both_eyes() {
+        for layout in [StereoLayout::SideBySide, StereoLayout::TopBottom] {
+            let image = layout.image_aspect_ratio(2.);
+            assert_eq!(layout.eye_aspect_ratio(image), 2.);
+        }
+    }
+
+    #[test]
+    fn test_eyes_converge() {
+        let (lookfrom, lookat, vup) = frame();
+        let layout = StereoLayout::SideBySide;
+        let camera = ThinLensCamera::new(
+            lookfrom,
+            lookat,
+            vup,
+            40.,
+            layout.eye_aspect_ratio(2.),
+            0.,
+            5.,
+        );
+        let stereo = StereoCamera::new(
+            Box::new(camera),
+            Frame::new(lookfrom, lookat, vup),
+            layout,
+            0.2,
+            4.,
+        );
+        let mut sampler = SamplerKind::Independent.create(1, 0);
+        let left = stereo.get_ray(0.25, 0.5, sampler.as_mut()).unwrap();
+        let right = stereo.get_ray(0.75, 0.5, sampler.as_mut()).unwrap();
+        assert_eq!(left.origin, Point3::new(-0.1, 0., 5.));
+        assert_eq!(right.origin, Point3::new(0.1, 0., 5.));
+
+        let convergence_point = Point3::new(0., 0., 1.);
+        for ray in [left, right] {
+            let t = (convergence_point.z - ray.origin.z) / ray.direction.z;
+            assert!((ray.at(t) - convergence_point).length() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_omnidirectional_baseline_turns() {
+        let (lookfrom, lookat, vup) = frame();
+        let stereo = StereoCamera::omnidirectional(
+            Box::new(EquirectangularCamera::new(lookfrom, lookat, vup)),
+            Frame::new(lookfrom, lookat, vup),
+            StereoLayout::TopBottom,
+            0.2,
+            f64::INFINITY,
+        );
+        let mut sampler = SamplerKind::Independent.create(1, 0);
+        // left eye, looking forward and then to the right
+        let forward = stereo.get_ray(0.5, 0.75, sampler.as_mut()).unwrap();
+        let to_the_right = stereo.get_ray(0.75, 0.75, sampler.as_mut()).unwrap();
+        assert!((forward.origin - Point3::new(-0.1, 0., 5.)).length() < 1e-9);
+        assert!((to_the_right.origin - Point3::new(0., 0., 4.9)).length() < 1e-9);
+    }
+}
@@ -0,0 +1,155 @@
+use crate::animation::TransformPath;
+use crate::hittable::{HitRecord, Hittable};
+use crate::ray::Ray;
+use crate::vec3::{Point3, Vec3};
+
+type ThreadHittable = dyn Hittable + Sync + Send;
+
+/// Places an object: scaled first, then rotated and then moved.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    /// degrees around the x, y and z axes, applied in that order
+    pub rotation: Vec3,
+    /// along the axes of the object, every component above zero
+    pub scale: Vec3,
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Self {
+            translation: Vec3::origin(),
+            rotation: Vec3::origin(),
+            scale: Vec3::new(1., 1., 1.),
+        }
+    }
+
+    /// rows of the rotation matrix, its transpose turns back
+    fn rotation_rows(&self) -> [Vec3; 3] {
+        let (sx, cx) = self.rotation.x.to_radians().sin_cos();
+        let (sy, cy) = self.rotation.y.to_radians().sin_cos();
+        let (sz, cz) = self.rotation.z.to_radians().sin_cos();
+        // Rz * Ry * Rx
+        [
+            Vec3::new(cz * cy, cz * sy * sx - sz * cx, cz * sy * cx + sz * sx),
+            Vec3::new(sz * cy, sz * sy * sx + cz * cx, sz * sy * cx - cz * sx),
+            Vec3::new(-sy, cy * sx, cy * cx),
+        ]
+    }
+
+    pub fn apply_point(&self, point: Point3) -> Point3 {
+        rotate(&self.rotation_rows(), point * self.scale) + self.translation
+    }
+}
+
+fn rotate(rows: &[Vec3; 3], vector: Vec3) -> Vec3 {
+    Vec3::new(
+        rows[0].dot(&vector),
+        rows[1].dot(&vector),
+        rows[2].dot(&vector),
+    )
+}
+
+fn rotate_back(rows: &[Vec3; 3], vector: Vec3) -> Vec3 {
+    vector.x * rows[0] + vector.y * rows[1] + vector.z * rows[2]
+}
+
+fn divide(vector: Vec3, by: Vec3) -> Vec3 {
+    Vec3::new(vector.x / by.x, vector.y / by.y, vector.z / by.z)
+}
+
+/// An object moving along a `TransformPath`, placed where it is at the time of each ray.
+pub struct Animated {
+    object: Box<ThreadHittable>,
+    path: TransformPath,
+}
+
+impl Animated {
+    pub fn new(object: Box<ThreadHittable>, path: TransformPath) -> Self {
+        Self { object, path }
+    }
+}
+
+impl Hittable for Animated {
+    fn hit(&self, ray: &Ray, min: f64, max: f64) -> Option<HitRecord<'_>> {
+        let transform = self.path.at(ray.time);
+        let rows = transform.rotation_rows();
+        // the ray in the space of the object keeps its parameter t
+        let local = Ray::with_time(
+            divide(
+                rotate_back(&rows, ray.origin - transform.translation),
+                transform.scale,
+            ),
+            divide(rotate_back(&rows, ray.direction), transform.scale),
+            ray.time,
+        );
+        let mut record = self.object.hit(&local, min, max)?;
+        record.point = ray.at(record.t);
+        // normals go with the inverse transpose, the inverse scale
+        record.normal = rotate(&rows, divide(record.normal, transform.scale)).unit_vector();
+        Some(record)
+    }
+
+    fn center(&self, time: f64) -> Option<Point3> {
+        let center = self.object.center(time)?;
+        Some(self.path.at(time).apply_point(center))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::{Interpolation, Keyframe};
+    use crate::materials::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::vec3::Color;
+
+    #[test]
+    fn test_rotation_turns_back() {
+        let transform = Transform {
+            translation: Vec3::origin(),
+            rotation: Vec3::new(30., -70., 115.),
+            scale: Vec3::new(1., 1., 1.),
+        };
+        let rows = transform.rotation_rows();
+        let vector = Vec3::new(0.3, -2., 1.5);
+        assert!((rotate_back(&rows, rotate(&rows, vector)) - vector).length() < 1e-12);
+        // a quarter turn around y takes x to -z
+        let quarter = Transform {
+            rotation: Vec3::new(0., 90., 0.),
+            ..transform
+        };
+        let turned = quarter.apply_point(Point3::new(1., 0., 0.));
+        assert!((turned - Point3::new(0., 0., -1.)).length() < 1e-12);
+    }
+
+    #[test]
+    fn test_animated_sphere_moves() {
+        let sphere = Sphere::new(
+            Point3::origin(),
+            1.,
+            Box::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        );
+        let key = |frame: f64, x: f64| Keyframe {
+            frame,
+            value: Transform {
+                translation: Vec3::new(x, 0., 0.),
+                scale: Vec3::new(1., 2., 1.),
+                ..Transform::identity()
+            },
+        };
+        let path = TransformPath::new(vec![key(0., 0.), key(10., 4.)], Interpolation::Linear);
+        let animated = Animated::new(Box::new(sphere), path.unwrap());
+        assert_eq!(animated.center(5.), Some(Point3::new(2., 0., 0.)));
+
+        let down = Vec3::new(0., -1., 0.);
+        let at_start = Ray::with_time(Point3::new(0., 5., 0.), down, 0.);
+        let record = animated.hit(&at_start, 0.001, f64::INFINITY).unwrap();
+        // stretched to twice the height
+        assert!((record.point - Point3::new(0., 2., 0.)).length() < 1e-12);
+        assert!((record.normal - Vec3::new(0., 1., 0.)).length() < 1e-12);
+        // by frame 10 the sphere has moved out of the way
+        let at_end = Ray::with_time(Point3::new(0., 5., 0.), down, 10.);
+        assert!(animated.hit(&at_end, 0.001, f64::INFINITY).is_none());
+    }
+}
@@ -1,11 +1,14 @@
 use rand::Rng;
+use std::time::Duration;
+
+use crate::vec3::Color;
 
 pub fn compare_floats_eps(left: f64, right: f64, epsilon: f64) -> bool {
-    return (left - right).abs() < epsilon;
+    (left - right).abs() < epsilon
 }
 
 pub fn compare_floats(left: f64, right: f64) -> bool {
-    return compare_floats_eps(left, right, 0.000_001);
+    compare_floats_eps(left, right, 0.000_001)
 }
 
 pub fn clamp(x: f64, min: f64, max: f64) -> f64 {
@@ -18,16 +21,188 @@ pub fn clamp(x: f64, min: f64, max: f64) -> f64 {
     }
 }
 
-pub fn random_double() -> f64 {
+pub fn random_double(rng: &mut impl Rng) -> f64 {
     // returns a random real in [0,1)
-    rand::thread_rng().gen::<f64>()
+    rng.gen::<f64>()
 }
 
-pub fn random_double_range(min: f64, max: f64) -> f64 {
+pub fn random_double_range(rng: &mut impl Rng, min: f64, max: f64) -> f64 {
     // returns a random real in [min,max)
-    min + (max - min) * random_double()
+    min + (max - min) * random_double(rng)
 }
 
 pub fn degrees_to_radians(degrees: f64) -> f64 {
     degrees * std::f64::consts::PI / 180.
 }
+
+/// Parses durations like `90`, `45s`, `10m`, `1h30m` or `500ms`, a bare number is seconds.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    // negative, infinite and too long durations are rejected
+    let invalid = || format!("invalid duration '{}'", value);
+    if let Ok(seconds) = value.parse::<f64>() {
+        return Duration::try_from_secs_f64(seconds).map_err(|_| invalid());
+    }
+
+    let mut total = Duration::ZERO;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let number_end = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .ok_or_else(|| format!("missing unit in duration '{}'", value))?;
+        let unit_end = rest[number_end..]
+            .find(|c: char| c.is_ascii_digit())
+            .map_or(rest.len(), |index| number_end + index);
+        let number: f64 = rest[..number_end].parse().map_err(|_| invalid())?;
+        let seconds = match &rest[number_end..unit_end] {
+            "ms" => number / 1000.,
+            "s" => number,
+            "m" => number * 60.,
+            "h" => number * 3600.,
+            unit => return Err(format!("unknown unit '{}' in duration '{}'", unit, value)),
+        };
+        total = Duration::try_from_secs_f64(seconds)
+            .ok()
+            .and_then(|duration| total.checked_add(duration))
+            .ok_or_else(invalid)?;
+        rest = &rest[unit_end..];
+    }
+    Ok(total)
+}
+
+/// Parses shutter speeds written as a fraction like `1/125` or in seconds like `0.5`.
+pub fn parse_shutter(value: &str) -> Result<f64, String> {
+    let invalid = || format!("invalid shutter speed '{}'", value);
+    let seconds = match value.trim().split_once('/') {
+        Some((numerator, denominator)) => {
+            let numerator: f64 = numerator.trim().parse().map_err(|_| invalid())?;
+            let denominator: f64 = denominator.trim().parse().map_err(|_| invalid())?;
+            numerator / denominator
+        }
+        None => value.trim().parse().map_err(|_| invalid())?,
+    };
+    if seconds.is_finite() && seconds > 0. {
+        Ok(seconds)
+    } else {
+        Err(invalid())
+    }
+}
+
+/// parses a finite number above zero, like a size or a ratio
+pub fn parse_positive(value: &str) -> Result<f64, String> {
+    match value.trim().parse::<f64>() {
+        Ok(number) if number.is_finite() && number > 0. => Ok(number),
+        _ => Err(format!("'{}' is not a number above zero", value)),
+    }
+}
+
+/// parses a distance above zero, inf for one infinitely far away
+pub fn parse_distance(value: &str) -> Result<f64, String> {
+    match value.trim().parse::<f64>() {
+        Ok(number) if number > 0. => Ok(number),
+        _ => Err(format!("'{}' is not a distance above zero or inf", value)),
+    }
+}
+
+/// parses a pixel position written as "x,y"
+pub fn parse_pixel(value: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("invalid pixel '{}', expected x,y", value);
+    let (x, y) = value.split_once(',').ok_or_else(invalid)?;
+    let x = x.trim().parse().map_err(|_| invalid())?;
+    let y = y.trim().parse().map_err(|_| invalid())?;
+    Ok((x, y))
+}
+
+/// parses a color written as "r,g,b"
+pub fn parse_color(value: &str) -> Result<Color, String> {
+    let invalid = || format!("invalid color '{}', expected r,g,b", value);
+    let channels = value
+        .split(',')
+        .map(|channel| channel.trim().parse::<f64>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>, _>>()?;
+    match channels[..] {
+        [r, g, b] => Ok(Color::new(r, g, b)),
+        _ => Err(invalid()),
+    }
+}
+
+/// parses "name=value"
+pub fn parse_assignment(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("invalid '{}', expected name=value", value)),
+    }
+}
+
+/// parses an interval written as "start,end" with start <= end
+pub fn parse_interval(value: &str) -> Result<(f64, f64), String> {
+    let invalid = || format!("invalid interval '{}', expected start,end", value);
+    let (start, end) = value.split_once(',').ok_or_else(invalid)?;
+    let start: f64 = start.trim().parse().map_err(|_| invalid())?;
+    let end: f64 = end.trim().parse().map_err(|_| invalid())?;
+    if start <= end {
+        Ok((start, end))
+    } else {
+        Err(format!("interval '{}' ends before it starts", value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("10m"), Ok(Duration::from_secs(600)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert!(parse_duration("10 minutes").is_err());
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("-5").is_err());
+        assert!(parse_duration("inf").is_err());
+        assert!(parse_duration("NaN").is_err());
+        assert!(parse_duration(&format!("{}h", "9".repeat(400))).is_err());
+    }
+
+    #[test]
+    fn test_parse_shutter() {
+        assert_eq!(parse_shutter("1/125"), Ok(0.008));
+        assert_eq!(parse_shutter("2"), Ok(2.));
+        assert!(parse_shutter("1/0").is_err());
+        assert!(parse_shutter("fast").is_err());
+    }
+
+    #[test]
+    fn test_parse_positive() {
+        assert_eq!(parse_positive("1.5"), Ok(1.5));
+        assert!(parse_positive("0").is_err());
+        assert!(parse_positive("-2").is_err());
+        assert!(parse_positive("inf").is_err());
+        assert!(parse_positive("NaN").is_err());
+    }
+
+    #[test]
+    fn test_parse_distance() {
+        assert_eq!(parse_distance("2"), Ok(2.));
+        assert_eq!(parse_distance("inf"), Ok(f64::INFINITY));
+        assert!(parse_distance("0").is_err());
+        assert!(parse_distance("-inf").is_err());
+        assert!(parse_distance("NaN").is_err());
+    }
+
+    #[test]
+    fn test_parse_pixel() {
+        assert_eq!(parse_pixel("600, 400"), Ok((600, 400)));
+        assert!(parse_pixel("600").is_err());
+        assert!(parse_pixel("-1,2").is_err());
+    }
+
+    #[test]
+    fn test_parse_interval() {
+        assert_eq!(parse_interval("0, 0.5"), Ok((0., 0.5)));
+        assert!(parse_interval("0.5,0").is_err());
+        assert!(parse_interval("0.5").is_err());
+    }
+}
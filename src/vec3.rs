@@ -1,5 +1,7 @@
 use crate::utils;
+use rand::Rng;
 use std::cmp;
+use std::io::{self, Write};
 use std::ops;
 
 #[derive(Debug)]
@@ -33,46 +35,19 @@ impl Vec3 {
         Vec3 { x, y, z }
     }
 
-    pub fn random() -> Self {
+    pub fn random(rng: &mut impl Rng) -> Self {
         Self {
-            x: utils::random_double(),
-            y: utils::random_double(),
-            z: utils::random_double(),
+            x: utils::random_double(rng),
+            y: utils::random_double(rng),
+            z: utils::random_double(rng),
         }
     }
 
-    pub fn random_range(min: f64, max: f64) -> Self {
+    pub fn random_range(rng: &mut impl Rng, min: f64, max: f64) -> Self {
         Self {
-            x: utils::random_double_range(min, max),
-            y: utils::random_double_range(min, max),
-            z: utils::random_double_range(min, max),
-        }
-    }
-
-    pub fn random_in_unit_sphere() -> Self {
-        loop {
-            let random_sphere = Self::random_range(-1., 1.);
-            if random_sphere.length_squared() >= 1. {
-                continue;
-            }
-            return random_sphere;
-        }
-    }
-
-    pub fn random_unit_vector() -> Self {
-        Self::random_in_unit_sphere().unit_vector()
-    }
-
-    pub fn random_in_unit_disk() -> Self {
-        loop {
-            let p = Self {
-                x: utils::random_double_range(-1., 1.),
-                y: utils::random_double_range(-1., 1.),
-                z: 0.0,
-            };
-            if p.length_squared() < 1. {
-                return p;
-            }
+            x: utils::random_double_range(rng, min, max),
+            y: utils::random_double_range(rng, min, max),
+            z: utils::random_double_range(rng, min, max),
         }
     }
 
@@ -100,25 +75,31 @@ impl Vec3 {
         *self / self.length()
     }
 
-    pub fn write_color(color: Color, samples_per_pixel: i32) {
-        let scale = 1. / samples_per_pixel as f64;
+    /// writes the average of samples summed up to `color` with the total `weight`
+    pub fn write_color(out: &mut impl Write, color: Color, weight: f64) -> io::Result<()> {
+        let scale = if weight > 0. { 1. / weight } else { 0. };
 
         let red = Self::color_value(color.x, scale);
         let green = Self::color_value(color.y, scale);
         let blue = Self::color_value(color.z, scale);
 
-        print!("{} {} {} ", red, green, blue);
+        write!(out, "{} {} {} ", red, green, blue)
     }
 
     fn color_value(value: f64, scale: f64) -> i32 {
         const ROUNDING: f64 = 256.0;
 
-        // gamma-correct for gamma=2.0
-        let value = (value * scale).sqrt();
+        // gamma-correct for gamma=2.0, negative lobes of filters may give values below zero
+        let value = (value * scale).max(0.).sqrt();
         // Write the translated [0,255] value of each color component.
         (ROUNDING * utils::clamp(value, 0., 0.999)) as i32
     }
 
+    /// relative luminance of a linear rgb color
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.x + 0.7152 * self.y + 0.0722 * self.z
+    }
+
     pub fn near_zero(&self) -> bool {
         utils::compare_floats(self.x, 0.0)
             && utils::compare_floats(self.y, 0.0)
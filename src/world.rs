@@ -1,26 +1,102 @@
+use crate::animation::TransformPath;
+use crate::counters;
+use crate::error::{Error, Result};
 use crate::hittable::{HitRecord, Hittable};
 use crate::materials;
 use crate::ray::Ray;
 use crate::sphere::Sphere;
+use crate::transform::Animated;
 use crate::utils;
 use crate::vec3::{Color, Point3};
+use std::collections::{HashMap, HashSet};
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 
 type ThreadHittable = dyn Hittable + Sync + Send;
+type ThreadMaterial = dyn materials::Material + Sync + Send;
+
+/// Materials of the three large spheres of the cover scene.
+pub struct CoverMaterials {
+    pub glass: Box<ThreadMaterial>,
+    pub matte: Box<ThreadMaterial>,
+    pub metal: Box<ThreadMaterial>,
+}
+
+impl Default for CoverMaterials {
+    fn default() -> Self {
+        Self {
+            glass: Box::new(materials::Dielectric::new(1.5)),
+            matte: Box::new(materials::Lambertian::new(Color {
+                x: 0.4,
+                y: 0.2,
+                z: 0.1,
+            })),
+            metal: Box::new(materials::Metal::new(
+                Color {
+                    x: 0.7,
+                    y: 0.6,
+                    z: 0.5,
+                },
+                0.1,
+            )),
+        }
+    }
+}
 
 pub struct World {
     list: Vec<Box<ThreadHittable>>,
+    /// positions in `list` of objects that can be looked up by name
+    names: HashMap<String, usize>,
+    /// positions in `list` of objects moving along a path
+    animated: HashSet<usize>,
 }
 
 impl World {
     pub fn new(list: Vec<Box<ThreadHittable>>) -> Self {
-        Self { list }
+        Self {
+            list,
+            names: HashMap::new(),
+            animated: HashSet::new(),
+        }
     }
 
     pub fn add(&mut self, value: Box<ThreadHittable>) {
         self.list.push(value)
     }
 
-    pub fn with_items() -> Self {
+    pub fn add_named(&mut self, name: &str, value: Box<ThreadHittable>) {
+        self.names.insert(name.to_string(), self.list.len());
+        self.add(value)
+    }
+
+    pub fn named(&self, name: &str) -> Option<&ThreadHittable> {
+        self.names.get(name).map(|&index| self.list[index].as_ref())
+    }
+
+    /// Makes the named object move along `path`, an object can only follow one.
+    pub fn animate(&mut self, name: &str, path: TransformPath) -> Result<()> {
+        let index = *self
+            .names
+            .get(name)
+            .ok_or_else(|| Error::UnknownObject(name.to_string()))?;
+        if !self.animated.insert(index) {
+            return Err(Error::AlreadyAnimated(name.to_string()));
+        }
+        let object = self.list.remove(index);
+        self.list
+            .insert(index, Box::new(Animated::new(object, path)));
+        Ok(())
+    }
+
+    /// Builds the cover scene, the same seed always gives the same spheres.
+    pub fn with_items(seed: u64) -> Self {
+        Self::with_materials(seed, CoverMaterials::default())
+    }
+
+    /// The cover scene with other materials for its large spheres.
+    pub fn with_materials(seed: u64, cover: CoverMaterials) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
         let mut the_world = Self::new(vec![]);
 
         let material_ground = materials::Lambertian::new(Color {
@@ -28,23 +104,26 @@ impl World {
             y: 0.5,
             z: 0.5,
         });
-        the_world.add(Box::new(Sphere::new(
-            Point3 {
-                x: 0.0,
-                y: -1000.,
-                z: 0.0,
-            },
-            1000.,
-            Box::new(material_ground),
-        )));
+        the_world.add_named(
+            "ground",
+            Box::new(Sphere::new(
+                Point3 {
+                    x: 0.0,
+                    y: -1000.,
+                    z: 0.0,
+                },
+                1000.,
+                Box::new(material_ground),
+            )),
+        );
 
         for a in -11..11 {
             for b in -11..11 {
-                let choose_mat = utils::random_double();
+                let choose_mat = utils::random_double(&mut rng);
                 let center = Point3 {
-                    x: a as f64 + 0.9 * utils::random_double(),
+                    x: a as f64 + 0.9 * utils::random_double(&mut rng),
                     y: 0.2,
-                    z: b as f64 + 0.9 * utils::random_double(),
+                    z: b as f64 + 0.9 * utils::random_double(&mut rng),
                 };
                 let another_point = Point3 {
                     x: 4.,
@@ -59,12 +138,12 @@ impl World {
                 let sphere_material: Box<dyn materials::Material + Send + Sync> =
                     if choose_mat < 0.8 {
                         // diffuse
-                        let albedo = Color::random() * Color::random();
+                        let albedo = Color::random(&mut rng) * Color::random(&mut rng);
                         Box::new(materials::Lambertian::new(albedo))
                     } else if choose_mat < 0.95 {
                         // metal
-                        let albedo = Color::random_range(0.5, 1.);
-                        let fuzz = utils::random_double_range(0., 0.5);
+                        let albedo = Color::random_range(&mut rng, 0.5, 1.);
+                        let fuzz = utils::random_double_range(&mut rng, 0., 0.5);
                         Box::new(materials::Metal::new(albedo, fuzz))
                     } else {
                         // glass
@@ -75,53 +154,52 @@ impl World {
             }
         }
 
-        the_world.add(Box::new(Sphere::new(
-            Point3 {
-                x: 0.0,
-                y: 1.0,
-                z: 0.0,
-            },
-            1.,
-            Box::new(materials::Dielectric::new(1.5)),
-        )));
-        the_world.add(Box::new(Sphere::new(
-            Point3 {
-                x: -4.0,
-                y: 1.0,
-                z: 0.0,
-            },
-            1.,
-            Box::new(materials::Lambertian::new(Color {
-                x: 0.4,
-                y: 0.2,
-                z: 0.1,
-            })),
-        )));
-        the_world.add(Box::new(Sphere::new(
-            Point3 {
-                x: 4.0,
-                y: 1.0,
-                z: 0.0,
-            },
-            1.,
-            Box::new(materials::Metal::new(
-                Color {
-                    x: 0.7,
-                    y: 0.6,
-                    z: 0.5,
+        the_world.add_named(
+            "glass",
+            Box::new(Sphere::new(
+                Point3 {
+                    x: 0.0,
+                    y: 1.0,
+                    z: 0.0,
                 },
-                0.1,
+                1.,
+                cover.glass,
             )),
-        )));
+        );
+        the_world.add_named(
+            "matte",
+            Box::new(Sphere::new(
+                Point3 {
+                    x: -4.0,
+                    y: 1.0,
+                    z: 0.0,
+                },
+                1.,
+                cover.matte,
+            )),
+        );
+        the_world.add_named(
+            "metal",
+            Box::new(Sphere::new(
+                Point3 {
+                    x: 4.0,
+                    y: 1.0,
+                    z: 0.0,
+                },
+                1.,
+                cover.metal,
+            )),
+        );
 
         the_world
     }
 }
 
 impl Hittable for World {
-    fn hit(&self, ray: &Ray, min: f64, max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, min: f64, max: f64) -> Option<HitRecord<'_>> {
         let mut closest = max;
         let mut result: Option<HitRecord> = None;
+        counters::count_intersection_tests(self.list.len());
 
         for hittable in self.list.iter() {
             if let Some(record) = hittable.hit(ray, min, closest) {
@@ -132,3 +210,31 @@ impl Hittable for World {
         result
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::{Interpolation, Keyframe};
+    use crate::transform::Transform;
+
+    #[test]
+    fn test_animate_once() {
+        let mut the_world = World::with_items(1);
+        let path = || {
+            let key = Keyframe {
+                frame: 0.,
+                value: Transform::identity(),
+            };
+            TransformPath::new(vec![key], Interpolation::Linear).unwrap()
+        };
+        assert!(the_world.animate("glass", path()).is_ok());
+        assert!(matches!(
+            the_world.animate("glass", path()),
+            Err(Error::AlreadyAnimated(_))
+        ));
+        assert!(matches!(
+            the_world.animate("teapot", path()),
+            Err(Error::UnknownObject(_))
+        ));
+    }
+}